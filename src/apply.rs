@@ -0,0 +1,197 @@
+use crate::command::parse_command;
+use crate::message::Message;
+use crate::model::{Model, UserMessage};
+use crate::update::update;
+use std::thread;
+use std::time::Duration;
+
+/// Operations given on the command line, separated by `;`; `\;` stands for a
+/// `;` that's part of an operation, like in `sort cmd a \; b`.
+pub(crate) fn split_ops(ops: &str) -> Vec<String> {
+    let mut split = vec![];
+    let mut current = String::new();
+    let mut chars = ops.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(';')) => {
+                current.push(';');
+                chars.next();
+            }
+            (';', _) => split.push(std::mem::take(&mut current)),
+            (c, _) => current.push(c),
+        }
+    }
+    split.push(current);
+
+    split
+        .into_iter()
+        .map(|op| op.trim().to_string())
+        .filter(|op| !op.is_empty())
+        .collect()
+}
+
+/// Operations read from a file, one per line; lines starting with `#` are
+/// comments.
+pub(crate) fn ops_from_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|op| !op.is_empty() && !op.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Applies operations one after another, exactly as if they were typed into
+/// the command line in the TUI. Stops at the first one that fails; ones that
+/// have nothing to do (like removing duplicates when there aren't any) don't
+/// count as failing.
+pub(crate) fn apply_ops<S: AsRef<str>>(model: &mut Model, ops: &[S]) -> Result<(), String> {
+    for (n, op) in ops.iter().map(AsRef::as_ref).enumerate() {
+        let message = parse_command(op).map_err(|e| format!("op {} ({op}): {e}", n + 1))?;
+        run(model, message);
+        wait_for_sort_keys(model);
+
+        if let Some(UserMessage::Error(e)) = &model.message {
+            return Err(format!("op {} ({op}): {e}", n + 1));
+        }
+    }
+
+    Ok(())
+}
+
+fn run(model: &mut Model, message: Message) {
+    let mut next = Some(message);
+    while let Some(message) = next {
+        next = update(model, message);
+    }
+}
+
+// sorting by command computes keys in the background
+fn wait_for_sort_keys(model: &mut Model) {
+    while let Some(job) = model.key_job.as_ref() {
+        let results = job.poll();
+        match results.is_empty() {
+            true => thread::sleep(Duration::from_millis(5)),
+            false => run(model, Message::SortKeysComputed(results)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Lines, Target};
+
+    fn model_with(count: usize) -> Model {
+        let lines: Vec<String> = (1..=count).map(|n| n.to_string()).collect();
+        Model::default(Target::Stdout, Lines::from(&lines), false)
+    }
+
+    fn content(model: &Model) -> Vec<&str> {
+        model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn ops_are_applied_in_order() {
+        // GIVEN
+        let mut model = model_with(5);
+
+        // WHEN
+        let result = apply_ops(&mut model, &split_ops("move 4 0; swap 2 3; reverse 4,5"));
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(content(&model), vec!["4", "2", "1", "5", "3"]);
+    }
+
+    #[test]
+    fn the_first_failing_op_stops_the_rest() {
+        // GIVEN
+        let mut model = model_with(3);
+
+        // WHEN
+        let result = apply_ops(&mut model, &split_ops("swap 1 2; swap 1 9; swap 1 2"));
+
+        // THEN
+        assert_eq!(
+            result,
+            Err("op 2 (swap 1 9): index is out of range".to_string())
+        );
+        assert_eq!(content(&model), vec!["2", "1", "3"]);
+    }
+
+    #[test]
+    fn huge_counts_stop_at_the_end_of_the_list() {
+        // GIVEN
+        let mut model = model_with(3);
+
+        // WHEN
+        let result = apply_ops(&mut model, &["down 18446744073709551615"]);
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(content(&model), vec!["2", "3", "1"]);
+    }
+
+    #[test]
+    fn ops_with_nothing_to_do_are_not_failures() {
+        // GIVEN
+        let mut model = model_with(3);
+
+        // WHEN
+        let result = apply_ops(
+            &mut model,
+            &split_ops("dedupe; g/zzz/select; undo; swap 1 2"),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(content(&model), vec!["2", "1", "3"]);
+    }
+
+    #[test]
+    fn sorting_by_command_waits_for_the_keys() {
+        // GIVEN
+        let lines: Vec<String> = ["ccc", "a", "bb"].iter().map(|l| l.to_string()).collect();
+        let mut model = Model::default(Target::Stdout, Lines::from(&lines), false);
+
+        // WHEN
+        let result = apply_ops(&mut model, &["sort cmd printf '%s' {} | wc -c"]);
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(content(&model), vec!["a", "bb", "ccc"]);
+    }
+
+    #[test]
+    fn escaped_separators_stay_part_of_the_op() {
+        // GIVEN
+        let ops = r"sort cmd awk '{print $2}' \; echo {}; swap 1 2;";
+
+        // WHEN
+        let split = split_ops(ops);
+
+        // THEN
+        assert_eq!(
+            split,
+            vec!["sort cmd awk '{print $2}' ; echo {}", "swap 1 2"]
+        );
+    }
+
+    #[test]
+    fn ops_files_skip_comments_and_blank_lines() {
+        // GIVEN
+        let content = "# rotate\nmove 3 0\n\n  sort cmd echo {}; echo x  \n";
+
+        // WHEN
+        let ops = ops_from_file(content);
+
+        // THEN
+        assert_eq!(ops, vec!["move 3 0", "sort cmd echo {}; echo x"]);
+    }
+}
@@ -0,0 +1,409 @@
+use crate::common::{PromptKind, View};
+use crate::message::{EditKind, Message};
+use crate::model::DedupeOptions;
+use crate::search::Direction;
+use crate::sort::{KeySpec, SortKind};
+
+/// Commands that can be typed in after `:`, along with the words their first
+/// argument can be; used for completion.
+pub(crate) const COMMANDS: &[(&str, &[&str])] = &[
+    ("bottom", &[]),
+    ("dedupe", &["-i", "-w"]),
+    ("delete", &[]),
+    ("down", &[]),
+    ("edit", &[]),
+    ("filter", &[]),
+    ("first", &[]),
+    ("gather", &["above", "below"]),
+    ("half-page-down", &[]),
+    ("half-page-up", &[]),
+    ("help", &[]),
+    ("last", &[]),
+    ("move", &[]),
+    ("new-above", &[]),
+    ("new-below", &[]),
+    ("next", &[]),
+    ("next-match", &[]),
+    ("prev", &[]),
+    ("prev-match", &[]),
+    ("put", &["above", "below"]),
+    ("quit", &[]),
+    ("redo", &[]),
+    ("reverse", &[]),
+    ("search", &[]),
+    ("select", &["all", "invert", "none"]),
+    ("shuffle", &[]),
+    (
+        "sort",
+        &[
+            "alpha", "case", "natural", "number", "length", "reverse", "key", "cmd",
+        ],
+    ),
+    ("swap", &[]),
+    ("toggle", &[]),
+    ("top", &[]),
+    ("undo", &[]),
+    ("up", &[]),
+    ("visual", &[]),
+    ("write", &[]),
+    ("yank", &[]),
+];
+
+/// Turns a command into the message it stands for. Positions are 1-based, like
+/// the line numbers an editor shows.
+///
+/// ```text
+/// 12                  go to line 12
+/// move 3 10           move line 3 (even with a selection) below line 10 (0
+///                     moves it to the top)
+/// move 10             move the current line (or the selection) below line 10
+/// swap 2 3            swap lines 2 and 3
+/// reverse 5,20        reverse lines 5 to 20
+/// sort natural        sort (also: alpha, case, number, length, reverse, or
+///                     -i, -V, -n, -l), `sort key SPEC`, `sort cmd COMMAND`
+/// g/REGEX/select      select lines matching REGEX
+/// w [PATH]            write to the file, or to PATH
+/// ```
+pub(crate) fn parse_command(text: &str) -> Result<Message, String> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix("g/") {
+        return parse_global(rest);
+    }
+
+    let (name, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let rest = rest.trim();
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    if let Ok(line) = name.parse::<usize>() {
+        return Ok(Message::GoToItem(index(line)?));
+    }
+
+    let message = match (name, args.as_slice()) {
+        ("next", []) => Message::GoToNextItem,
+        ("prev", []) => Message::GoToPreviousPreview,
+        ("first", []) => Message::GoToFirstItem,
+        ("last", []) => Message::GoToLastItem,
+        ("half-page-down", []) => Message::ScrollHalfPage(Direction::Forward),
+        ("half-page-up", []) => Message::ScrollHalfPage(Direction::Backward),
+        ("next-match", []) => Message::GoToMatch(Direction::Forward),
+        ("prev-match", []) => Message::GoToMatch(Direction::Backward),
+        ("search", []) => Message::OpenPrompt(PromptKind::Search),
+        ("filter", []) => Message::OpenPrompt(PromptKind::Filter),
+        ("edit", []) => Message::OpenEdit(EditKind::Current),
+        ("new-below", []) => Message::OpenEdit(EditKind::NewBelow),
+        ("new-above", []) => Message::OpenEdit(EditKind::NewAbove),
+        ("down", _) => repeated(|| Message::SwitchWithNextItem, &args)?,
+        ("up", _) => repeated(|| Message::SwitchWithPreviousItem, &args)?,
+        ("top", []) => Message::MoveToTop,
+        ("bottom", []) => Message::MoveToBottom,
+        ("move", [to]) => Message::MoveBelow(number(to)?),
+        ("move", [from, to]) => Message::MoveItemBelow(index(number(from)?)?, number(to)?),
+        ("swap", [a, b]) => Message::SwapItems(index(number(a)?)?, index(number(b)?)?),
+        ("gather", [] | ["below"]) => Message::MoveSelectionNextToCursor(Direction::Forward),
+        ("gather", ["above"]) => Message::MoveSelectionNextToCursor(Direction::Backward),
+        ("toggle", []) => Message::ToggleSelection,
+        ("visual", []) => Message::ToggleVisualMode,
+        ("select", ["all"]) => Message::SelectAll,
+        ("select", ["invert"]) => Message::InvertSelection,
+        ("select", ["none"]) => Message::UnselectAll,
+        ("sort", _) => parse_sort(rest)?,
+        ("reverse", []) => Message::Sort(SortKind::Reverse),
+        ("reverse", [range]) => parse_range(range)?,
+        ("shuffle", []) => Message::Shuffle(None),
+        ("shuffle", [seed]) => Message::Shuffle(Some(
+            seed.parse().map_err(|_| format!("invalid seed: {seed}"))?,
+        )),
+        ("dedupe", _) => parse_dedupe(&args)?,
+        ("delete" | "d", _) => with_register(Message::DeleteLines, &args)?,
+        ("yank" | "y", _) => with_register(Message::YankLines, &args)?,
+        ("put" | "pu", _) => parse_put(&args)?,
+        ("undo" | "u", []) => Message::Undo,
+        ("redo", []) => Message::Redo,
+        ("write" | "w", []) => Message::SaveSelection,
+        ("write" | "w", _) => Message::WriteTo(rest.to_string()),
+        ("quit" | "q", []) => Message::Quit,
+        ("help", []) => Message::ShowView(View::Help),
+        ("", []) => return Err("no command given".to_string()),
+        (name, _) if COMMANDS.iter().any(|(known, _)| *known == name) => {
+            return Err(format!("invalid arguments for {name}: {rest}"));
+        }
+        (name, _) => return Err(format!("unknown command: {name}")),
+    };
+
+    Ok(message)
+}
+
+/// Completes the command name, or the argument after it, as far as it can be
+/// done unambiguously.
+pub(crate) fn complete(text: &str) -> Option<String> {
+    let (done, partial, words) = completion_context(text)?;
+    let candidates: Vec<&str> = words
+        .into_iter()
+        .filter(|word| word.starts_with(partial))
+        .collect();
+
+    let completed = match candidates.as_slice() {
+        [] => return None,
+        [only] => format!("{only} "),
+        [first, rest @ ..] => rest.iter().fold(first.to_string(), |prefix, word| {
+            prefix
+                .chars()
+                .zip(word.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        }),
+    };
+
+    Some(format!("{done}{completed}"))
+}
+
+/// Words that could complete what's being typed, when there are several (and
+/// something has been typed).
+pub(crate) fn candidates(text: &str) -> Vec<&'static str> {
+    if text.is_empty() {
+        return vec![];
+    }
+    let Some((_, partial, words)) = completion_context(text) else {
+        return vec![];
+    };
+    let matching: Vec<&str> = words
+        .into_iter()
+        .filter(|word| word.starts_with(partial))
+        .collect();
+
+    match matching.len() {
+        0 | 1 => vec![],
+        _ => matching,
+    }
+}
+
+// the text that's already complete, the word being typed, and the words it
+// could turn into
+fn completion_context(text: &str) -> Option<(&str, &str, Vec<&'static str>)> {
+    let Some((name, partial)) = text.split_once(' ') else {
+        return Some(("", text, COMMANDS.iter().map(|(name, _)| *name).collect()));
+    };
+    if partial.contains(' ') {
+        return None;
+    }
+
+    let (_, words) = COMMANDS.iter().find(|(known, _)| *known == name)?;
+    Some((&text[..name.len() + 1], partial, words.to_vec()))
+}
+
+fn parse_global(rest: &str) -> Result<Message, String> {
+    let Some((pattern, action)) = rest.rsplit_once('/') else {
+        return Err("usage: g/REGEX/select".to_string());
+    };
+    match action.trim() {
+        "select" | "s" => Ok(Message::SelectMatching(pattern.to_string())),
+        action => Err(format!("unknown action for g: {action}")),
+    }
+}
+
+fn parse_sort(rest: &str) -> Result<Message, String> {
+    let (kind, spec) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let spec = spec.trim();
+
+    let kind = match (kind, spec) {
+        ("" | "alpha", "") => SortKind::Lexical,
+        ("case" | "-i", "") => SortKind::IgnoreCase,
+        ("natural" | "-V", "") => SortKind::Natural,
+        ("number" | "-n", "") => SortKind::Numeric,
+        ("length" | "-l", "") => SortKind::Length,
+        ("reverse", "") => SortKind::Reverse,
+        ("key", spec) => {
+            KeySpec::parse(spec).map_err(|e| format!("invalid sort key: {e}"))?;
+            return Ok(Message::SortByKey(spec.to_string()));
+        }
+        ("cmd", "") => return Err("usage: sort cmd COMMAND".to_string()),
+        ("cmd", command) => return Ok(Message::SortByCommand(command.to_string())),
+        _ => return Err(format!("unknown sort: {rest}")),
+    };
+
+    Ok(Message::Sort(kind))
+}
+
+fn parse_range(range: &str) -> Result<Message, String> {
+    let (start, end) = range
+        .split_once(',')
+        .ok_or_else(|| format!("invalid range: {range}"))?;
+    let (start, end) = (index(number(start)?)?, index(number(end)?)?);
+    if start > end {
+        return Err(format!("invalid range: {range}"));
+    }
+
+    Ok(Message::ReverseRange(start, end))
+}
+
+fn parse_dedupe(args: &[&str]) -> Result<Message, String> {
+    let mut options = DedupeOptions::default();
+    for arg in args {
+        match *arg {
+            "-i" => options.ignore_case = true,
+            "-w" => options.trim = true,
+            arg => return Err(format!("unknown option for dedupe: {arg}")),
+        }
+    }
+
+    Ok(Message::Dedupe(options))
+}
+
+fn parse_put(args: &[&str]) -> Result<Message, String> {
+    let (direction, register) = match args {
+        ["above", register @ ..] => (Direction::Backward, register),
+        ["below", register @ ..] => (Direction::Forward, register),
+        register => (Direction::Forward, register),
+    };
+
+    with_register(Message::Paste(direction), register)
+}
+
+// `delete a` works like `"add`
+fn with_register(message: Message, args: &[&str]) -> Result<Message, String> {
+    let mut chars = match args {
+        [] => return Ok(message),
+        [name] => name.chars(),
+        _ => return Err(format!("expected a register, got: {}", args.join(" "))),
+    };
+
+    match (chars.next(), chars.next()) {
+        (Some(name), None) if name.is_ascii_alphabetic() => {
+            Ok(Message::Batch(vec![Message::SelectRegister(name), message]))
+        }
+        _ => Err(format!("invalid register: {}", args.join(" "))),
+    }
+}
+
+// the count is capped when the message runs, like one typed in before a key
+fn repeated(message: fn() -> Message, args: &[&str]) -> Result<Message, String> {
+    let times = match args {
+        [] => return Ok(message()),
+        [times] => number(times)?,
+        _ => return Err(format!("expected a count, got: {}", args.join(" "))),
+    };
+
+    Ok(Message::WithCount(times, Box::new(message())))
+}
+
+fn number(text: &str) -> Result<usize, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("invalid number: {text}"))
+}
+
+// 1-based position -> index
+fn index(position: usize) -> Result<usize, String> {
+    position
+        .checked_sub(1)
+        .ok_or_else(|| "positions start at 1".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_are_one_based() {
+        // GIVEN
+        let commands = ["12", "move 3 10", "move 0", "swap 2 3", "reverse 5,20"];
+        let invalid = ["swap 0 1", "reverse 20,5"];
+
+        // WHEN
+        let parsed = commands.map(parse_command);
+        let rejected = invalid.map(|command| parse_command(command).is_err());
+
+        // THEN
+        assert_eq!(
+            parsed,
+            [
+                Ok(Message::GoToItem(11)),
+                Ok(Message::MoveItemBelow(2, 10)),
+                Ok(Message::MoveBelow(0)),
+                Ok(Message::SwapItems(1, 2)),
+                Ok(Message::ReverseRange(4, 19)),
+            ]
+        );
+        assert_eq!(rejected, [true, true]);
+    }
+
+    #[test]
+    fn commands_take_arguments() {
+        // GIVEN
+        let commands = [
+            "sort natural",
+            "sort -n",
+            "sort key -t , 2n",
+            "g/^feat/select",
+            "w /tmp/out.txt",
+            "put above a",
+            "down 2",
+        ];
+
+        // WHEN
+        let parsed = commands.map(parse_command);
+
+        // THEN
+        assert_eq!(
+            parsed,
+            [
+                Ok(Message::Sort(SortKind::Natural)),
+                Ok(Message::Sort(SortKind::Numeric)),
+                Ok(Message::SortByKey("-t , 2n".to_string())),
+                Ok(Message::SelectMatching("^feat".to_string())),
+                Ok(Message::WriteTo("/tmp/out.txt".to_string())),
+                Ok(Message::Batch(vec![
+                    Message::SelectRegister('a'),
+                    Message::Paste(Direction::Backward)
+                ])),
+                Ok(Message::WithCount(2, Box::new(Message::SwitchWithNextItem))),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_commands_are_reported() {
+        // GIVEN
+        let commands = ["frobnicate", "select some", "sort key 0", "g/x/delete"];
+
+        // WHEN
+        let parsed = commands.map(parse_command);
+
+        // THEN
+        assert_eq!(parsed[0], Err("unknown command: frobnicate".to_string()));
+        assert_eq!(
+            parsed[1],
+            Err("invalid arguments for select: some".to_string())
+        );
+        assert!(parsed[2].is_err());
+        assert!(parsed[3].is_err());
+    }
+
+    #[test]
+    fn completion_extends_names_and_arguments() {
+        // GIVEN
+        let partial = ["rev", "s", "sh", "sort na", "select ", "xyz"];
+
+        // WHEN
+        let completed = partial.map(complete);
+
+        // THEN
+        assert_eq!(
+            completed,
+            [
+                Some("reverse ".to_string()),
+                Some("s".to_string()),
+                Some("shuffle ".to_string()),
+                Some("sort natural ".to_string()),
+                Some("select ".to_string()),
+                None,
+            ]
+        );
+        assert_eq!(candidates("sw"), Vec::<&str>::new());
+        assert_eq!(
+            candidates("s"),
+            vec!["search", "select", "shuffle", "sort", "swap"]
+        );
+    }
+}
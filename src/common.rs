@@ -1,9 +1,17 @@
-use ratatui::style::Color;
+use std::time::Duration;
 
-pub const TITLE_FG_COLOR: Color = Color::from_u32(0x282828);
-pub const PRIMARY_COLOR: Color = Color::from_u32(0xd3869b);
-pub const SELECTED_COLOR: Color = Color::from_u32(0x83a598);
 pub const TITLE: &str = " shfl ";
+pub const STDIN_PATH: &str = "-";
+/// How long messages stay in the status bar
+pub const MESSAGE_TIMEOUT: Duration = Duration::from_secs(4);
+pub const CONFLICT_PROMPT: &str =
+    " file changed on disk: [o]verwrite / [r]eload / [m]erge new lines / [esc] cancel ";
+pub const SORT_MENU: &str =
+    " sort: [a]lpha [i]gnore case [n]atural [N]umber [l]ength [k]ey [c]ommand [r]everse [esc] ";
+pub const DEDUPE_MENU: &str =
+    " remove duplicates: [d] exact / [i]gnoring case / ignoring [w]hitespace / [b]oth / [esc] ";
+pub const REGISTER_MENU: &str = " register: [a-z] / [A-Z] to append / [esc] cancel ";
+pub const SELECT_MENU: &str = " select: [a]ll / [i]nvert / matching [r]egex / [esc] cancel ";
 pub const UNEXPECTED_ERROR_MESSAGE: &str =
     "something unexpected happened, please let @dhth know via https://github.com/dhth/shfl/issues";
 
@@ -12,3 +20,22 @@ pub(crate) enum View {
     List,
     Help,
 }
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum PromptKind {
+    Search,
+    Filter,
+    SortKey,
+    SortCommand,
+    Select,
+    Command,
+}
+
+/// Key menus shown in the bottom bar, where the next key picks an action
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum Menu {
+    Sort,
+    Dedupe,
+    Register,
+    Select,
+}
@@ -0,0 +1,146 @@
+use crate::keymap::Keymap;
+use crate::theme::{ColorOverrides, Theme, ThemeName, no_color, theme_for_background};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Contents of the config file; every section is optional.
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Keys (like `"ctrl+s"` or `"g g"`) bound to actions or commands
+    #[serde(default)]
+    pub(crate) keys: BTreeMap<String, String>,
+    /// Picked from the terminal's background if not given
+    pub(crate) theme: Option<ThemeName>,
+    /// Colors replacing the ones of the theme
+    #[serde(default)]
+    pub(crate) colors: ColorOverrides,
+}
+
+impl Config {
+    pub(crate) fn keymap(&self) -> Result<Keymap, String> {
+        Keymap::with_bindings(&self.keys)
+    }
+
+    pub(crate) fn theme(&self) -> Result<Theme, String> {
+        let name = self
+            .theme
+            .or_else(|| theme_for_background(&std::env::var("COLORFGBG").ok()?))
+            .unwrap_or(ThemeName::Dark);
+        let mut theme = Theme::named(name).with_overrides(&self.colors)?;
+        theme.monochrome = no_color();
+        Ok(theme)
+    }
+}
+
+/// Reads the config at the given path, or at the default location if none
+/// is given. A missing file at the default location just means there's no
+/// config.
+pub(crate) fn load_config(path: Option<&str>) -> anyhow::Result<Config> {
+    let (path, explicit) = match path {
+        Some(path) => (PathBuf::from(path), true),
+        None => match default_config_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+
+    if !explicit && !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("couldn't read config at {}: {e}", path.display()))?;
+    parse_config(&content)
+        .map_err(|e| anyhow::anyhow!("invalid config at {}:\n{e}", path.display()))
+}
+
+// both the syntax and the keymap are checked, so mistakes show up on startup
+fn parse_config(content: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(content).map_err(|e| e.to_string())?;
+    let errors: Vec<String> = [config.keymap().err(), config.theme().err()]
+        .into_iter()
+        .flatten()
+        .collect();
+
+    match errors.is_empty() {
+        true => Ok(config),
+        false => Err(errors.join("\n")),
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_dir.join("shfl").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_in_the_config_are_validated() {
+        // GIVEN
+        let content = r#"
+[keys]
+"ctrl+s" = "write"
+"x" = "explode"
+"#;
+
+        // WHEN
+        let result = parse_config(content);
+
+        // THEN
+        let error = result.expect_err("config should be invalid");
+        assert!(error.contains("\"x\""), "error: {error}");
+    }
+
+    #[test]
+    fn themes_and_colors_are_read() {
+        // GIVEN
+        let content = r#"
+theme = "light"
+
+[colors]
+primary = "magenta"
+"#;
+
+        // WHEN
+        let theme = parse_config(content)
+            .and_then(|config| config.theme())
+            .expect("config should be valid");
+
+        // THEN
+        assert_eq!(theme.primary, ratatui::style::Color::Magenta);
+        assert_eq!(theme.text, Theme::named(ThemeName::Light).text);
+    }
+
+    #[test]
+    fn unknown_sections_are_rejected() {
+        // GIVEN
+        let content = "[colours]\nprimary = \"red\"\n";
+
+        // WHEN
+        let result = parse_config(content);
+
+        // THEN
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn an_empty_config_uses_the_default_keymap() {
+        // GIVEN
+        let content = "";
+
+        // WHEN
+        let config = parse_config(content).expect("config should be valid");
+
+        // THEN
+        assert!(config.keys.is_empty());
+    }
+}
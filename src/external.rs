@@ -0,0 +1,187 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const MAX_WORKERS: usize = 8;
+pub(crate) const PLACEHOLDER: &str = "{}";
+
+/// Sort keys being computed in the background, by running a shell command once
+/// per line on a pool of worker threads.
+#[derive(Debug)]
+pub(crate) struct KeyJob {
+    pub(crate) command: String,
+    pub(crate) total: usize,
+    pub(crate) done: usize,
+    pub(crate) failed: usize,
+    results: Receiver<KeyResult>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// The key computed for a line; `Err` holds why the command failed.
+pub(crate) type KeyResult = (Vec<u8>, Result<String, String>);
+
+impl KeyJob {
+    /// Starts computing keys for `lines`, each given as its raw bytes and its
+    /// decoded content.
+    pub(crate) fn start(command: &str, lines: Vec<(Vec<u8>, String)>) -> Self {
+        let total = lines.len();
+        let queue = Arc::new(Mutex::new(lines));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, results) = mpsc::channel();
+
+        let workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_WORKERS)
+            .min(total.max(1));
+
+        for _ in 0..workers {
+            let queue = Arc::clone(&queue);
+            let cancelled = Arc::clone(&cancelled);
+            let sender = sender.clone();
+            let command = command.to_string();
+
+            thread::spawn(move || {
+                while !cancelled.load(Ordering::Relaxed) {
+                    let next = queue.lock().ok().and_then(|mut q| q.pop());
+                    let Some((raw, content)) = next else {
+                        break;
+                    };
+                    let key = run_key_command(&command, &content);
+                    if sender.send((raw, key)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self {
+            command: command.to_string(),
+            total,
+            done: 0,
+            failed: 0,
+            results,
+            cancelled,
+        }
+    }
+
+    /// Keys that have been computed since the last call.
+    pub(crate) fn poll(&self) -> Vec<KeyResult> {
+        self.results.try_iter().collect()
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.done >= self.total
+    }
+
+    /// Stops workers from picking up more lines; commands that are already
+    /// running are left to finish on their own.
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for KeyJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Runs `command` through `sh` for a line, and returns its trimmed output. The
+/// line replaces every `{}` in the command (quoted for the shell); commands
+/// without a `{}` get the line on stdin instead.
+pub(crate) fn run_key_command(command: &str, line: &str) -> Result<String, String> {
+    let uses_placeholder = command.contains(PLACEHOLDER);
+    let script = match uses_placeholder {
+        true => command.replace(PLACEHOLDER, &shell_quote(line)),
+        false => command.to_string(),
+    };
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&script)
+        .stdin(match uses_placeholder {
+            true => Stdio::null(),
+            false => Stdio::piped(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // commands are free to not read their input
+        let _ = writeln!(stdin, "{line}");
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn lines_are_quoted_for_the_shell() {
+        // GIVEN
+        let line = "it's $HOME; `ls`";
+
+        // WHEN
+        let key = run_key_command("printf '%s' {} | wc -c", line);
+
+        // THEN
+        assert_eq!(key, Ok("16".to_string()));
+    }
+
+    #[test]
+    fn commands_without_a_placeholder_read_the_line_from_stdin() {
+        // GIVEN
+        let line = "shfl";
+
+        // WHEN
+        let key = run_key_command("tr a-z A-Z", line);
+        let failed = run_key_command("exit 3", line);
+
+        // THEN
+        assert_eq!(key, Ok("SHFL".to_string()));
+        assert!(failed.is_err());
+    }
+
+    #[test]
+    fn key_job_computes_a_key_for_every_line() {
+        // GIVEN
+        let lines: Vec<(Vec<u8>, String)> = (0..20)
+            .map(|n| (n.to_string().into_bytes(), n.to_string()))
+            .collect();
+        let mut job = KeyJob::start("echo {}{}", lines);
+
+        // WHEN
+        let mut results = vec![];
+        let started = Instant::now();
+        while !job.is_finished() && started.elapsed() < Duration::from_secs(10) {
+            let batch = job.poll();
+            job.done += batch.len();
+            results.extend(batch);
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // THEN
+        results.sort_by_key(|(raw, _)| String::from_utf8_lossy(raw).parse::<u32>().unwrap());
+        let keys: Vec<String> = results.into_iter().map(|(_, k)| k.unwrap()).collect();
+        let expected: Vec<String> = (0..20).map(|n| format!("{n}{n}")).collect();
+        assert_eq!(keys, expected);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::model::{LineItem, Lines};
+use std::collections::VecDeque;
+
+const MAX_ENTRIES: usize = 500;
+
+/// A point-in-time copy of the list: line order, selection state and cursor
+/// position. Lines share their contents with the list, so a snapshot costs a
+/// few words per line.
+#[derive(Debug, Clone)]
+pub(crate) struct Snapshot {
+    items: Vec<LineItem>,
+    cursor: Option<usize>,
+}
+
+impl Snapshot {
+    pub(crate) fn of(lines: &Lines) -> Self {
+        Self {
+            items: lines.items.clone(),
+            cursor: lines.cursor(),
+        }
+    }
+
+    /// Whether the lines have been reordered (or changed) since the snapshot
+    /// was taken. Cursor movement and selection changes alone don't count.
+    pub(crate) fn differs_from(&self, lines: &Lines) -> bool {
+        self.items.len() != lines.items.len()
+            || self
+                .items
+                .iter()
+                .zip(lines.items.iter())
+                .any(|(a, b)| a.raw != b.raw)
+    }
+
+    pub(crate) fn restore(self, lines: &mut Lines) {
+        lines.items = self.items;
+        if let Some(i) = self.cursor {
+            lines.set_cursor(i);
+        }
+    }
+}
+
+/// Undo/redo stacks of snapshots taken before every change to the lines.
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    pub(crate) fn record(&mut self, before: Snapshot) {
+        if self.undo.len() == MAX_ENTRIES {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(before);
+        self.redo.clear();
+    }
+
+    pub(crate) fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    pub(crate) fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_oldest_entries_are_dropped_once_the_history_is_full() {
+        // GIVEN
+        let lines: Vec<String> = (0..=MAX_ENTRIES).map(|n| n.to_string()).collect();
+        let mut history = History::default();
+
+        // WHEN
+        for i in 0..=MAX_ENTRIES {
+            let mut lines = Lines::from(&lines);
+            lines.set_cursor(i);
+            history.record(Snapshot::of(&lines));
+        }
+
+        // THEN
+        let current = Snapshot::of(&Lines::from(&lines));
+        let mut cursors = vec![];
+        while let Some(snapshot) = history.undo(current.clone()) {
+            cursors.push(snapshot.cursor);
+        }
+        assert_eq!(cursors.len(), MAX_ENTRIES);
+        assert_eq!(cursors.first(), Some(&Some(MAX_ENTRIES)));
+        assert_eq!(cursors.last(), Some(&Some(1)));
+    }
+}
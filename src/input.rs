@@ -0,0 +1,76 @@
+/// Edits that can be made to a [`TextInput`]
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum InputAction {
+    Insert(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Clear,
+}
+
+/// A single line of editable text with a cursor.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct TextInput {
+    value: String,
+    // position of the cursor, in chars
+    cursor: usize,
+}
+
+impl TextInput {
+    /// An input holding `value`, with the cursor at its end.
+    pub(crate) fn with_value(value: &str) -> Self {
+        Self {
+            value: value.to_string(),
+            cursor: value.chars().count(),
+        }
+    }
+
+    pub(crate) fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub(crate) fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub(crate) fn apply(&mut self, action: InputAction) {
+        match action {
+            InputAction::Insert(c) => {
+                self.value.insert(self.byte_index(self.cursor), c);
+                self.cursor += 1;
+            }
+            InputAction::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.value.remove(self.byte_index(self.cursor));
+                }
+            }
+            InputAction::Delete => {
+                if self.cursor < self.value.chars().count() {
+                    self.value.remove(self.byte_index(self.cursor));
+                }
+            }
+            InputAction::Left => self.cursor = self.cursor.saturating_sub(1),
+            InputAction::Right => {
+                self.cursor = (self.cursor + 1).min(self.value.chars().count());
+            }
+            InputAction::Home => self.cursor = 0,
+            InputAction::End => self.cursor = self.value.chars().count(),
+            InputAction::Clear => {
+                self.value.clear();
+                self.cursor = 0;
+            }
+        }
+    }
+
+    fn byte_index(&self, char_index: usize) -> usize {
+        self.value
+            .char_indices()
+            .nth(char_index)
+            .map(|(i, _)| i)
+            .unwrap_or(self.value.len())
+    }
+}
@@ -0,0 +1,691 @@
+use crate::command::parse_command;
+use crate::common::{Menu, PromptKind};
+use crate::message::{EditKind, Message};
+use crate::search::Direction;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Keys bound by default, as they'd be written in the config file.
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("J", "down"),
+    ("K", "up"),
+    ("Enter", "top"),
+    ("B", "bottom"),
+    ("m", "gather"),
+    ("M", "gather above"),
+    ("j", "next"),
+    ("Down", "next"),
+    ("k", "prev"),
+    ("Up", "prev"),
+    ("ctrl+d", "half-page-down"),
+    ("ctrl+u", "half-page-up"),
+    ("g", "first"),
+    ("G", "last"),
+    ("w", "write"),
+    ("u", "undo"),
+    ("ctrl+r", "redo"),
+    ("space", "toggle"),
+    ("s", "toggle"),
+    ("V", "visual"),
+    ("v", "select-menu"),
+    ("e", "edit"),
+    ("o", "new-below"),
+    ("a", "new-below"),
+    ("O", "new-above"),
+    ("i", "new-above"),
+    ("d d", "delete"),
+    ("y y", "yank"),
+    ("p", "put"),
+    ("P", "put above"),
+    ("\"", "register"),
+    ("/", "search"),
+    ("n", "next-match"),
+    ("N", "prev-match"),
+    ("f", "filter"),
+    (":", "command-line"),
+    ("S", "sort-menu"),
+    ("D", "dedupe-menu"),
+    ("R", "shuffle"),
+    ("?", "help"),
+    ("Esc", "quit"),
+    ("q", "quit"),
+];
+
+// an action's name, the message it sends, and its description in the help view
+type Action = (&'static str, fn() -> Message, &'static str);
+
+/// Actions keys can be bound to, in the order they're listed in the help view.
+/// Keys can also be bound to any command of the `:` command line.
+const ACTIONS: &[Action] = &[
+    (
+        "down",
+        || Message::SwitchWithNextItem,
+        "move item (or every selected item) one position below",
+    ),
+    (
+        "up",
+        || Message::SwitchWithPreviousItem,
+        "move item (or every selected item) one position above",
+    ),
+    (
+        "top",
+        || Message::MoveToTop,
+        "move item/selection to the start of the list",
+    ),
+    (
+        "bottom",
+        || Message::MoveToBottom,
+        "move item/selection to the end of the list",
+    ),
+    (
+        "gather",
+        || Message::MoveSelectionNextToCursor(Direction::Forward),
+        "move the selection below the current item",
+    ),
+    (
+        "gather above",
+        || Message::MoveSelectionNextToCursor(Direction::Backward),
+        "move the selection above the current item",
+    ),
+    ("next", || Message::GoToNextItem, "go down"),
+    ("prev", || Message::GoToPreviousPreview, "go up"),
+    (
+        "half-page-down",
+        || Message::ScrollHalfPage(Direction::Forward),
+        "go down half a page",
+    ),
+    (
+        "half-page-up",
+        || Message::ScrollHalfPage(Direction::Backward),
+        "go up half a page",
+    ),
+    (
+        "first",
+        || Message::GoToFirstItem,
+        "go to the start of the list",
+    ),
+    (
+        "last",
+        || Message::GoToLastItem,
+        "go to the end of the list",
+    ),
+    (
+        "write",
+        || Message::SaveSelection,
+        "write to file (print to stdout in pipe mode)",
+    ),
+    ("undo", || Message::Undo, "undo last change"),
+    ("redo", || Message::Redo, "redo last undone change"),
+    (
+        "toggle",
+        || Message::ToggleSelection,
+        "select/unselect item",
+    ),
+    (
+        "visual",
+        || Message::ToggleVisualMode,
+        "visual mode: select the lines between where it started and\nthe cursor (again: done, esc: cancel)",
+    ),
+    (
+        "select-menu",
+        || Message::OpenMenu(Menu::Select),
+        "select [a]ll lines, [i]nvert the selection, or select\nlines matching a [r]egex",
+    ),
+    (
+        "edit",
+        || Message::OpenEdit(EditKind::Current),
+        "edit the current line (enter: save, esc: cancel)",
+    ),
+    (
+        "new-below",
+        || Message::OpenEdit(EditKind::NewBelow),
+        "add a new line below the current one",
+    ),
+    (
+        "new-above",
+        || Message::OpenEdit(EditKind::NewAbove),
+        "add a new line above the current one",
+    ),
+    (
+        "delete",
+        || Message::DeleteLines,
+        "cut the current line (or the selected lines)",
+    ),
+    (
+        "yank",
+        || Message::YankLines,
+        "yank (copy) the current line (or the selected lines)",
+    ),
+    (
+        "put",
+        || Message::Paste(Direction::Forward),
+        "paste below the current line",
+    ),
+    (
+        "put above",
+        || Message::Paste(Direction::Backward),
+        "paste above the current line",
+    ),
+    (
+        "register",
+        || Message::OpenMenu(Menu::Register),
+        "use a register ([a-z], or [A-Z] to append to it) for the\nnext cut, yank or paste",
+    ),
+    (
+        "search",
+        || Message::OpenPrompt(PromptKind::Search),
+        "search (ctrl+r: toggle regex, ctrl+t: toggle ignoring case)",
+    ),
+    (
+        "next-match",
+        || Message::GoToMatch(Direction::Forward),
+        "go to next match",
+    ),
+    (
+        "prev-match",
+        || Message::GoToMatch(Direction::Backward),
+        "go to previous match",
+    ),
+    (
+        "filter",
+        || Message::OpenPrompt(PromptKind::Filter),
+        "filter lines, fuzzily (ctrl+r: toggle regex); moves still\napply to the full list",
+    ),
+    (
+        "command-line",
+        || Message::OpenPrompt(PromptKind::Command),
+        "run a command (tab: complete, up/down: history)",
+    ),
+    (
+        "sort-menu",
+        || Message::OpenMenu(Menu::Sort),
+        "sort the list (or only the selected lines, among their\nown positions): [a]lphabetically, [i]gnoring case,\n[n]aturally (v1.9 before v1.10), [N]umerically,\nby [l]ength, by [k]ey, by the output of a [c]ommand run\nfor each line, or [r]everse the current order",
+    ),
+    (
+        "dedupe-menu",
+        || Message::OpenMenu(Menu::Dedupe),
+        "remove duplicate lines, keeping the first occurrence: [d]\nexact, ignoring [i] case, [w] surrounding whitespace,\nor [b] both; lines that show up more than once are\nmarked with their number of occurrences",
+    ),
+    (
+        "shuffle",
+        || Message::Shuffle(None),
+        "shuffle the list (or only the selected lines); the seed is\nshown so the shuffle can be reproduced with --seed",
+    ),
+    (
+        "help",
+        || Message::ShowView(crate::common::View::Help),
+        "show/hide help view",
+    ),
+    (
+        "quit",
+        || Message::Quit,
+        "go back/reset selection/clear search/clear filter/exit",
+    ),
+];
+
+/// Width of the column keys are listed in, in the help view
+const KEYS_COLUMN_WIDTH: usize = 21;
+
+/// A key along with the modifiers held down with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub(crate) fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // shift is already part of the character typed
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parses chords like `J`, `ctrl+s`, `alt+Enter` or `space`.
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = text.split('+').collect();
+        // `+` itself, or a chord ending in it
+        if text.ends_with("++") || text == "+" {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let (key, modifier_names) = parts.split_last().ok_or_else(|| "empty key".to_string())?;
+
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier in {text}: {name}")),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "space" => KeyCode::Char(' '),
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').map(str::parse::<u8>) {
+                    Some(Ok(n)) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key: {key}")),
+                },
+            },
+        };
+
+        // terminals send shifted characters as the character itself, so
+        // `shift+j` is `J`
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => match c {
+                'a'..='z' | 'A'..='Z' => KeyCode::Char(c.to_ascii_uppercase()),
+                _ => {
+                    return Err(format!(
+                        "shift only goes with letters in {text}; use the character typed instead"
+                    ));
+                }
+            },
+            code => code,
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// What the keys pressed so far add up to.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Lookup<'a> {
+    Action(&'a str),
+    /// The start of a longer binding
+    Prefix,
+    Unbound,
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    keys: Vec<KeyChord>,
+    action: String,
+}
+
+/// Key sequences in the list view, and the actions they're bound to.
+#[derive(Debug, Clone)]
+pub(crate) struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .map(|(keys, action)| Binding {
+                keys: parse_keys(keys).expect("default keys should be valid"),
+                action: action.to_string(),
+            })
+            .collect();
+
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// The default keymap, with keys bound (or unbound, with the action
+    /// `none`) as given. Default bindings that would get in the way of the
+    /// ones given are dropped. Every problem found is reported, one per line.
+    pub(crate) fn with_bindings(overrides: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut keymap = Self::default();
+        let mut custom: Vec<Binding> = vec![];
+        let mut errors = vec![];
+
+        for (keys, action) in overrides {
+            let parsed = match parse_keys(keys) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    errors.push(format!("\"{keys}\": {e}"));
+                    continue;
+                }
+            };
+            keymap.bindings.retain(|b| !overlaps(&b.keys, &parsed));
+            if action == "none" {
+                continue;
+            }
+            if let Err(e) = action_message(action) {
+                errors.push(format!("\"{keys}\": {e}"));
+                continue;
+            }
+            if let Some(other) = custom.iter().find(|b| overlaps(&b.keys, &parsed)) {
+                errors.push(format!(
+                    "\"{keys}\" and \"{}\" can't both be bound, since one starts with the other",
+                    display_keys(&other.keys)
+                ));
+                continue;
+            }
+            custom.push(Binding {
+                keys: parsed,
+                action: action.clone(),
+            });
+        }
+
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        keymap.bindings.extend(custom);
+        Ok(keymap)
+    }
+
+    pub(crate) fn lookup(&self, keys: &[KeyChord]) -> Lookup<'_> {
+        let mut lookup = Lookup::Unbound;
+        for binding in &self.bindings {
+            if binding.keys == keys {
+                return Lookup::Action(&binding.action);
+            }
+            if binding.keys.starts_with(keys) {
+                lookup = Lookup::Prefix;
+            }
+        }
+        lookup
+    }
+
+    /// Lines of the help view: the keys bound to each action, and what it
+    /// does.
+    pub(crate) fn help(&self) -> Vec<String> {
+        let mut lines = vec![];
+        for (action, _, description) in ACTIONS {
+            push_help_entry(&mut lines, &self.keys_for(action), description);
+        }
+
+        let mut commands: Vec<&str> = vec![];
+        for binding in &self.bindings {
+            let action = binding.action.as_str();
+            if !ACTIONS.iter().any(|(name, _, _)| *name == action) && !commands.contains(&action) {
+                commands.push(action);
+            }
+        }
+        for command in commands {
+            push_help_entry(&mut lines, &self.keys_for(command), &format!(":{command}"));
+        }
+
+        if let (Some(down), Some(top)) =
+            (self.keys_for("down").first(), self.keys_for("top").first())
+        {
+            push_help_entry(
+                &mut lines,
+                &["[N]".to_string()],
+                &format!(
+                    "repeat a move N times ({down} moves down N positions); with\n{top}, move to position N instead"
+                ),
+            );
+        }
+
+        lines
+    }
+
+    fn keys_for(&self, action: &str) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|b| b.action == action)
+            .map(|b| display_keys(&b.keys))
+            .collect()
+    }
+}
+
+/// The message an action (or a command) stands for.
+pub(crate) fn action_message(action: &str) -> Result<Message, String> {
+    if let Some((_, message, _)) = ACTIONS.iter().find(|(name, _, _)| *name == action) {
+        return Ok(message());
+    }
+
+    parse_command(action)
+        .map_err(|e| format!("\"{action}\" isn't an action or a valid command ({e})"))
+}
+
+// sequences are chords separated by spaces, like `g g`
+fn parse_keys(keys: &str) -> Result<Vec<KeyChord>, String> {
+    let chords = keys
+        .split(' ')
+        .filter(|chord| !chord.is_empty())
+        .map(KeyChord::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match chords.is_empty() {
+        true => Err("no keys given".to_string()),
+        false => Ok(chords),
+    }
+}
+
+fn display_keys(keys: &[KeyChord]) -> String {
+    keys.iter()
+        .map(KeyChord::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// a sequence can't be bound if it's the start of another one, or the other way
+// round
+fn overlaps(a: &[KeyChord], b: &[KeyChord]) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+fn push_help_entry(lines: &mut Vec<String>, keys: &[String], description: &str) {
+    if keys.is_empty() {
+        return;
+    }
+
+    let keys = keys.join(" / ");
+    let mut description = description.lines();
+    let first = description.next().unwrap_or_default();
+    lines.push(format!(
+        "{keys:<width$}{first}",
+        width = KEYS_COLUMN_WIDTH.max(keys.len() + 1)
+    ));
+    for line in description {
+        lines.push(format!("{:width$}  {line}", "", width = KEYS_COLUMN_WIDTH));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, a)| (k.to_string(), a.to_string()))
+            .collect()
+    }
+
+    fn chords(keys: &str) -> Vec<KeyChord> {
+        parse_keys(keys).unwrap()
+    }
+
+    #[test]
+    fn chords_are_parsed() {
+        // GIVEN
+        let chords = ["ctrl+s", "J", "ctrl++", "Alt+Enter", "ctlr+s", "enterr"];
+
+        // WHEN
+        let parsed = chords.map(KeyChord::parse);
+
+        // THEN
+        assert_eq!(
+            parsed[..4],
+            [
+                Ok(KeyChord::new(KeyCode::Char('s'), KeyModifiers::CONTROL)),
+                Ok(KeyChord::new(KeyCode::Char('J'), KeyModifiers::SHIFT)),
+                Ok(KeyChord::new(KeyCode::Char('+'), KeyModifiers::CONTROL)),
+                Ok(KeyChord::new(KeyCode::Enter, KeyModifiers::ALT)),
+            ]
+        );
+        assert!(parsed[4].is_err());
+        assert!(parsed[5].is_err());
+    }
+
+    #[test]
+    fn shift_with_a_letter_is_the_uppercase_letter() {
+        // GIVEN
+        let chords = ["shift+j", "ctrl+shift+k", "shift+1"];
+
+        // WHEN
+        let parsed = chords.map(KeyChord::parse);
+
+        // THEN
+        assert_eq!(
+            parsed[..2],
+            [
+                Ok(KeyChord::new(KeyCode::Char('J'), KeyModifiers::NONE)),
+                Ok(KeyChord::new(KeyCode::Char('K'), KeyModifiers::CONTROL)),
+            ]
+        );
+        assert!(parsed[2].is_err());
+    }
+
+    #[test]
+    fn default_keymap_binds_sequences() {
+        // GIVEN
+        let keymap = Keymap::default();
+
+        // WHEN
+        let lookups = ["J", "d", "d d", "d x"].map(|keys| keymap.lookup(&chords(keys)));
+
+        // THEN
+        assert_eq!(
+            lookups,
+            [
+                Lookup::Action("down"),
+                Lookup::Prefix,
+                Lookup::Action("delete"),
+                Lookup::Unbound
+            ]
+        );
+    }
+
+    #[test]
+    fn bindings_override_the_defaults_in_their_way() {
+        // GIVEN
+        let overrides = bindings(&[
+            ("ctrl+s", "write"),
+            ("d", "half-page-down"),
+            ("g g", "first"),
+            ("q", "none"),
+            ("ctrl+n", "sort natural"),
+        ]);
+
+        // WHEN
+        let keymap = Keymap::with_bindings(&overrides).expect("bindings should be valid");
+
+        // THEN
+        assert_eq!(keymap.lookup(&chords("ctrl+s")), Lookup::Action("write"));
+        assert_eq!(keymap.lookup(&chords("w")), Lookup::Action("write"));
+        assert_eq!(
+            keymap.lookup(&chords("d")),
+            Lookup::Action("half-page-down")
+        );
+        assert_eq!(keymap.lookup(&chords("g")), Lookup::Prefix);
+        assert_eq!(keymap.lookup(&chords("q")), Lookup::Unbound);
+        assert_eq!(
+            keymap.lookup(&chords("ctrl+n")),
+            Lookup::Action("sort natural")
+        );
+    }
+
+    #[test]
+    fn invalid_bindings_are_all_reported() {
+        // GIVEN
+        let overrides = bindings(&[
+            ("ctlr+s", "write"),
+            ("x", "frobnicate"),
+            ("z", "first"),
+            ("z z", "last"),
+        ]);
+
+        // WHEN
+        let errors = Keymap::with_bindings(&overrides).unwrap_err();
+
+        // THEN
+        let errors: Vec<&str> = errors.lines().collect();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("unknown modifier in ctlr+s: ctlr"));
+        assert!(errors[1].contains("frobnicate"));
+        assert!(errors[2].contains("can't both be bound"));
+    }
+
+    #[test]
+    fn actions_have_a_command_that_does_the_same() {
+        // GIVEN
+        // menus and the command line itself are covered by the commands they
+        // lead to, like `sort` or `select`
+        let actions = ACTIONS.iter().filter(|(name, _, _)| {
+            !name.ends_with("-menu") && !["register", "command-line"].contains(name)
+        });
+
+        // WHEN
+        let differing: Vec<&str> = actions
+            .filter(|(name, message, _)| parse_command(name) != Ok(message()))
+            .map(|(name, _, _)| *name)
+            .collect();
+
+        // THEN
+        assert!(differing.is_empty(), "no command for: {differing:?}");
+    }
+
+    #[test]
+    fn help_lists_the_active_bindings() {
+        // GIVEN
+        let overrides = bindings(&[("ctrl+s", "write"), ("ctrl+n", "sort natural")]);
+        let keymap = Keymap::with_bindings(&overrides).unwrap();
+
+        // WHEN
+        let help = keymap.help();
+
+        // THEN
+        assert!(help.contains(
+            &"w / ctrl+s           write to file (print to stdout in pipe mode)".to_string()
+        ));
+        assert!(help.contains(
+            &"d d                  cut the current line (or the selected lines)".to_string()
+        ));
+        assert!(help.contains(&"ctrl+n               :sort natural".to_string()));
+    }
+}
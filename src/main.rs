@@ -1,64 +1,283 @@
+mod apply;
+mod command;
 mod common;
+mod config;
+mod external;
+mod history;
+mod input;
+mod keymap;
 mod message;
 mod model;
+mod register;
+mod search;
+mod shuffle;
+mod sort;
+mod terminal;
+mod theme;
 mod update;
 mod utils;
 mod view;
+mod watch;
 
 use anyhow::Context;
-use clap::Parser;
-use common::UNEXPECTED_ERROR_MESSAGE;
-use model::{Model, RunningState, UserMessage};
-use std::fs::File;
+use apply::{apply_ops, ops_from_file, split_ops};
+use clap::{Parser, Subcommand};
+use common::{STDIN_PATH, UNEXPECTED_ERROR_MESSAGE};
+use config::load_config;
+use message::Message;
+use model::{LineItem, Lines, Model, NewLinesPosition, RunningState, Target, UserMessage};
+use ratatui::{Terminal, backend::Backend};
+use shuffle::random_seed;
+use std::io::{IsTerminal, Write};
+use terminal::{init_tty, restore_tty};
 use update::{handle_event, update};
-use utils::read_from_file;
+use utils::{
+    BackupMode, Encoding, FileFormat, Fingerprint, LineRef, RawLine, read_from_file,
+    read_lines_with_fingerprint,
+};
 use view::view;
+use watch::FileWatcher;
 
 /// shfl lets you easily rearrange lines in a file with simple keymaps
 #[derive(Parser, Debug)]
-#[command(about, long_about=None)]
+#[command(
+    about,
+    long_about=None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// File path
-    #[arg(value_name = "STRING")]
-    path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// File path; use "-" to read lines from stdin and print the new order to stdout
+    #[arg(value_name = "STRING", required = true)]
+    path: Option<String>,
     /// If set, shfl will save the new order of lines on exit
     #[arg(short = 's', long = "save-on-exit", value_name = "STRING")]
     save_on_exit: bool,
+    /// If set, shfl will back up the previous contents of the file before every write
+    #[arg(
+        short = 'b',
+        long = "backup",
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "simple"
+    )]
+    backup: Option<BackupMode>,
+    /// If set, shfl will reload the file when it changes on disk, keeping the current order
+    #[arg(short = 'w', long = "watch")]
+    watch: bool,
+    /// Where lines added to the file while shfl is running are placed
+    #[arg(long = "new-lines", value_name = "POSITION", default_value = "bottom")]
+    new_lines_at: NewLinesPosition,
+    /// Encoding used to display lines; lines are always saved with their original bytes
+    #[arg(
+        short = 'e',
+        long = "encoding",
+        value_name = "ENCODING",
+        default_value = "utf-8"
+    )]
+    encoding: Encoding,
+    /// If set, shfl will shuffle the lines and print them to stdout, without opening the TUI
+    #[arg(long = "shuffle", conflicts_with = "watch")]
+    shuffle: bool,
+    /// Seed for --shuffle; the same seed always shuffles the same lines the same way
+    #[arg(long = "seed", value_name = "NUMBER", requires = "shuffle")]
+    seed: Option<u64>,
+    /// Config file to use instead of $XDG_CONFIG_HOME/shfl/config.toml
+    #[arg(long = "config", value_name = "PATH")]
+    config: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Apply operations to a file without opening the TUI, and write the result
+    Apply(ApplyArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ApplyArgs {
+    /// File path; use "-" to read lines from stdin and print the result to stdout
+    #[arg(value_name = "STRING")]
+    path: String,
+    /// Operations to apply, separated by ";" (write "\;" for a ";" inside one); these are the
+    /// commands of the ":" command line
+    #[arg(
+        long = "ops",
+        value_name = "STRING",
+        required_unless_present = "ops_file",
+        conflicts_with = "ops_file"
+    )]
+    ops: Option<String>,
+    /// File to read operations from, one per line; lines starting with "#" are ignored
+    #[arg(long = "ops-file", value_name = "PATH")]
+    ops_file: Option<String>,
+    /// If set, the result is printed to stdout instead of being written to the file
+    #[arg(long = "stdout")]
+    stdout: bool,
+    /// If set, shfl will back up the previous contents of the file before writing
+    #[arg(
+        short = 'b',
+        long = "backup",
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "simple"
+    )]
+    backup: Option<BackupMode>,
+    /// Encoding used to match and sort lines; lines are always saved with their original bytes
+    #[arg(
+        short = 'e',
+        long = "encoding",
+        value_name = "ENCODING",
+        default_value = "utf-8"
+    )]
+    encoding: Encoding,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let file = File::open(&args.path)
-        .with_context(|| format!("couldn't open file at the provided path: {}", &args.path))?;
+    if let Some(Command::Apply(apply_args)) = args.command {
+        return apply(apply_args);
+    }
+    let path = args.path.unwrap_or_default();
+    if args.watch && path == STDIN_PATH {
+        anyhow::bail!("--watch can't be used when reading from stdin");
+    }
+
+    let config = load_config(args.config.as_deref())?;
+    let keymap = config.keymap().map_err(|e| anyhow::anyhow!(e))?;
+    let theme = config.theme().map_err(|e| anyhow::anyhow!(e))?;
 
-    let lines = read_from_file(&file).with_context(|| {
-        format!(
-            "couldn't read data from file at the provided path: {}",
-            &args.path
-        )
-    })?;
+    let ((lines, format), target, fingerprint) = read_input(path)?;
 
-    let mut terminal = ratatui::init();
-    terminal.clear().context(UNEXPECTED_ERROR_MESSAGE)?;
+    let mut model = Model::default(target, Lines::new(lines, args.encoding), args.save_on_exit);
+    model.keymap = keymap;
+    model.theme = theme;
+    model.backup = args.backup;
+    model.fingerprint = fingerprint;
+    model.format = format;
+    model.new_lines_at = args.new_lines_at;
+    if args.watch
+        && let Target::File(path) = &model.target
+    {
+        model.watcher = Some(FileWatcher::new(path));
+    }
+
+    if args.shuffle {
+        let seed = args.seed.unwrap_or_else(random_seed);
+        let _ = update(&mut model, Message::Shuffle(Some(seed)));
+        let lines: Vec<LineRef> = model
+            .lines
+            .items
+            .iter()
+            .map(LineItem::as_line_ref)
+            .collect();
+        model.output = Some(model.format.join(&lines));
+        if args.seed.is_none() {
+            eprintln!("seed: {seed}");
+        }
+    } else {
+        match model.target {
+            Target::File(_) => {
+                let mut terminal = ratatui::init();
+                run(&mut terminal, &mut model)?;
+                ratatui::try_restore().context(UNEXPECTED_ERROR_MESSAGE)?;
+            }
+            Target::Stdout => {
+                let mut terminal = init_tty().context("couldn't open the terminal at /dev/tty")?;
+                run(&mut terminal, &mut model)?;
+                restore_tty().context(UNEXPECTED_ERROR_MESSAGE)?;
+            }
+        }
+    }
+
+    if let Some(UserMessage::Error(msg)) = &model.message {
+        eprintln!("error: {msg}");
+    }
+
+    print_output(&model)
+}
 
-    let mut model = Model::default(args.path, &lines, args.save_on_exit);
+type Input = ((Vec<RawLine>, FileFormat), Target, Option<Fingerprint>);
+
+fn read_input(path: String) -> anyhow::Result<Input> {
+    if path == STDIN_PATH {
+        let stdin = std::io::stdin();
+        if stdin.is_terminal() {
+            anyhow::bail!("nothing was piped to stdin");
+        }
+        let document = read_from_file(stdin.lock()).context("couldn't read data from stdin")?;
+        return Ok((document, Target::Stdout, None));
+    }
+
+    let (lines, format, fingerprint) = read_lines_with_fingerprint(&path)
+        .with_context(|| format!("couldn't read data from file at the provided path: {path}"))?;
+    Ok(((lines, format), Target::File(path), Some(fingerprint)))
+}
+
+// saving works the same way as pressing `w` in the TUI, so in pipe mode (and
+// with --stdout) a selection limits what's printed
+fn apply(args: ApplyArgs) -> anyhow::Result<()> {
+    let ops_content = match &args.ops_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("couldn't read operations from file: {path}"))?,
+        None => String::new(),
+    };
+    let ops = match &args.ops {
+        Some(ops) => split_ops(ops),
+        None => ops_from_file(&ops_content),
+    };
+
+    let ((lines, format), target, fingerprint) = read_input(args.path)?;
+    let mut model = Model::default(target, Lines::new(lines, args.encoding), false);
+    model.backup = args.backup;
+    model.fingerprint = fingerprint;
+    model.format = format;
+    if args.stdout {
+        model.target = Target::Stdout;
+    }
+
+    apply_ops(&mut model, &ops).map_err(|e| anyhow::anyhow!(e))?;
+
+    let _ = update(&mut model, Message::SaveSelection);
+    if model.conflict.is_some() {
+        anyhow::bail!("the file changed on disk while operations were being applied");
+    }
+    if let Some(UserMessage::Error(msg)) = &model.message {
+        anyhow::bail!("{msg}");
+    }
+
+    print_output(&model)
+}
+
+fn print_output(model: &Model) -> anyhow::Result<()> {
+    if let Some(output) = &model.output {
+        std::io::stdout()
+            .lock()
+            .write_all(output)
+            .context("couldn't write to stdout")?;
+    }
+
+    Ok(())
+}
+
+fn run<B: Backend>(terminal: &mut Terminal<B>, model: &mut Model) -> anyhow::Result<()>
+where
+    B::Error: Send + Sync + 'static,
+{
+    terminal.clear().context(UNEXPECTED_ERROR_MESSAGE)?;
 
     while model.running_state != RunningState::Done {
         terminal
-            .draw(|f| view(&mut model, f))
+            .draw(|f| view(model, f))
             .context(UNEXPECTED_ERROR_MESSAGE)?;
-        let mut current_msg = handle_event(&model).context(UNEXPECTED_ERROR_MESSAGE)?;
+        let mut current_msg = handle_event(model).context(UNEXPECTED_ERROR_MESSAGE)?;
 
         while current_msg.is_some() {
-            current_msg = update(&mut model, current_msg.unwrap());
+            current_msg = update(model, current_msg.unwrap());
         }
     }
 
-    ratatui::try_restore().context(UNEXPECTED_ERROR_MESSAGE)?;
-    if let Some(UserMessage::Error(msg)) = &model.message {
-        println!("error: {msg}");
-    }
-
     Ok(())
 }
@@ -1,17 +1,104 @@
-use crate::common::View;
+use crate::common::{Menu, PromptKind, View};
+use crate::external::KeyResult;
+use crate::input::InputAction;
+use crate::keymap::KeyChord;
+use crate::model::DedupeOptions;
+use crate::search::Direction;
+use crate::sort::SortKind;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Debug)]
 pub(crate) enum Message {
+    /// A key that starts a longer key binding
+    PendingKey(KeyChord),
+    ClearPendingKeys,
+    /// Adds a digit to the count for the next command
+    AppendToCount(usize),
     MoveToIndex(usize),
+    /// Moves the current item (or the selection) right below the first n
+    /// items, like vim's `:move n`
+    MoveBelow(usize),
+    /// Moves the item at an index right below the first n items, leaving the
+    /// selection alone
+    MoveItemBelow(usize, usize),
+    SwapItems(usize, usize),
+    /// Reverses the items between two indices, both included
+    ReverseRange(usize, usize),
+    GoToItem(usize),
     GoToNextItem,
     GoToPreviousPreview,
     GoToFirstItem,
     GoToLastItem,
+    ScrollHalfPage(Direction),
     SwitchWithNextItem,
     SwitchWithPreviousItem,
     MoveToTop,
+    MoveToBottom,
+    /// Moves the selection right below (forward) or above (backward) the
+    /// cursor
+    MoveSelectionNextToCursor(Direction),
     ToggleSelection,
+    ToggleVisualMode,
+    SelectAll,
+    InvertSelection,
+    UnselectAll,
+    SelectMatching(String),
+    Undo,
+    Redo,
     SaveSelection,
+    WriteTo(String),
     ShowView(View),
+    OpenPrompt(PromptKind),
+    EditPrompt(InputAction),
+    SubmitPrompt,
+    CancelPrompt,
+    ToggleRegex,
+    ToggleSearchIgnoreCase,
+    CompleteCommand,
+    /// Goes to a newer (forward) or older (backward) command in the history
+    RecallCommand(Direction),
+    GoToMatch(Direction),
+    OpenMenu(Menu),
+    CloseMenu,
+    Sort(SortKind),
+    SortByKey(String),
+    SortByCommand(String),
+    /// Shuffles with the given seed, or a random one
+    Shuffle(Option<u64>),
+    SortKeysComputed(Vec<KeyResult>),
+    CancelSortKeys,
+    Dedupe(DedupeOptions),
+    OpenEdit(EditKind),
+    EditLine(InputAction),
+    SubmitEdit,
+    CancelEdit,
+    DeleteLines,
+    SelectRegister(char),
+    YankLines,
+    /// Pastes below (forward) or above (backward) the cursor
+    Paste(Direction),
+    ResolveConflict(ConflictResolution),
+    FileChangedOnDisk,
     Quit,
+    /// Messages run one after another, and undone in one go
+    Batch(Vec<Message>),
+    /// A message run with a count, as if it had been typed in first
+    WithCount(usize, Box<Message>),
+}
+
+/// Which line typing goes into
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum EditKind {
+    Current,
+    NewAbove,
+    NewBelow,
+}
+
+/// What to do when the file has been changed by something else since it was
+/// read
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum ConflictResolution {
+    Overwrite,
+    Reload,
+    Merge,
+    Cancel,
 }
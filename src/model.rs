@@ -1,44 +1,210 @@
-use crate::common::{SELECTED_COLOR, View};
-use ratatui::{
-    style::Style,
-    text::Line,
-    widgets::{ListItem, ListState},
-};
+use crate::common::{Menu, PromptKind, View};
+use crate::external::KeyJob;
+use crate::history::History;
+use crate::input::TextInput;
+use crate::keymap::{KeyChord, Keymap};
+use crate::register::Registers;
+use crate::search::{Direction, Filter, Search};
+use crate::sort::KeySpec;
+use crate::theme::Theme;
+use crate::utils::{BackupMode, Encoding, FileFormat, Fingerprint, LineEnding, LineRef, RawLine};
+use crate::watch::FileWatcher;
+use clap::ValueEnum;
+use ratatui::widgets::ListState;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::Instant;
 
 #[derive(Debug)]
 pub(crate) struct Model {
     pub(crate) view: View,
     pub(crate) running_state: RunningState,
-    pub(crate) file_path: String,
+    pub(crate) target: Target,
     pub(crate) lines: Lines,
-    pub(crate) selected_count: usize,
     pub(crate) message: Option<UserMessage>,
+    /// When the message goes away on its own
+    pub(crate) message_expires_at: Option<Instant>,
     pub(crate) save_on_exit: bool,
+    pub(crate) backup: Option<BackupMode>,
+    pub(crate) history: History,
+    /// What's printed to stdout once the terminal is restored
+    pub(crate) output: Option<Vec<u8>>,
+    pub(crate) fingerprint: Option<Fingerprint>,
+    pub(crate) format: FileFormat,
+    pub(crate) conflict: Option<Conflict>,
+    pub(crate) watcher: Option<FileWatcher>,
+    /// The file changed on disk while a line was being edited or the prompt
+    /// was open; it's reloaded once they're closed
+    pub(crate) reload_pending: bool,
+    pub(crate) new_lines_at: NewLinesPosition,
+    pub(crate) prompt: Option<Prompt>,
+    pub(crate) search: Option<Search>,
+    pub(crate) menu: Option<Menu>,
+    pub(crate) key_job: Option<KeyJob>,
+    pub(crate) edit: Option<LineEdit>,
+    pub(crate) visual: Option<Visual>,
+    pub(crate) registers: Registers<RawLine>,
+    /// Register picked for the next cut, yank or paste
+    pub(crate) pending_register: Option<char>,
+    /// Number typed before a command, like the 5 in `5J`
+    pub(crate) count: Option<usize>,
+    /// Commands run from the command line, oldest first
+    pub(crate) command_history: Vec<String>,
+    pub(crate) keymap: Keymap,
+    pub(crate) theme: Theme,
+    /// Keys pressed so far of a binding that takes several
+    pub(crate) partial_keys: Vec<KeyChord>,
+    /// Number of lines the list showed when it was last drawn
+    pub(crate) list_height: usize,
+    /// Lines as they were last read from, or written to, the file
+    pub(crate) saved: Vec<Rc<[u8]>>,
+    /// Seed of the last shuffle, kept in the status bar so it can be repeated
+    pub(crate) last_seed: Option<u64>,
+    /// Keys computed by sort commands: command -> raw line -> key
+    pub(crate) key_cache: HashMap<String, HashMap<Vec<u8>, String>>,
 }
 
 impl Model {
-    pub(crate) fn default(file_path: String, lines: &Vec<String>, save_on_exit: bool) -> Self {
+    pub(crate) fn default(target: Target, lines: Lines, save_on_exit: bool) -> Self {
+        let saved = lines.items.iter().map(|item| item.raw.clone()).collect();
         Self {
             view: View::List,
             running_state: RunningState::Running,
-            file_path,
-            lines: Lines::from(lines),
-            selected_count: 0,
+            target,
+            lines,
             message: None,
+            message_expires_at: None,
             save_on_exit,
+            backup: None,
+            history: History::default(),
+            output: None,
+            fingerprint: None,
+            format: FileFormat::default(),
+            conflict: None,
+            watcher: None,
+            reload_pending: false,
+            new_lines_at: NewLinesPosition::Bottom,
+            prompt: None,
+            search: None,
+            menu: None,
+            key_job: None,
+            edit: None,
+            visual: None,
+            registers: Registers::default(),
+            pending_register: None,
+            count: None,
+            command_history: vec![],
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            partial_keys: vec![],
+            list_height: 0,
+            saved,
+            last_seed: None,
+            key_cache: HashMap::new(),
         }
     }
+
+    /// Whether the lines differ from what's in the file, in content or in
+    /// order.
+    pub(crate) fn is_modified(&self) -> bool {
+        self.lines
+            .items
+            .iter()
+            .map(|item| &item.raw)
+            .ne(self.saved.iter())
+    }
+}
+
+/// Where the new order of lines ends up when it's saved.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Target {
+    /// Written back to the file the lines were read from.
+    File(String),
+    /// Printed to stdout once the user confirms (pipe mode).
+    Stdout,
+}
+
+/// Text being typed into the bottom bar
+#[derive(Debug)]
+pub(crate) struct Prompt {
+    pub(crate) kind: PromptKind,
+    pub(crate) input: TextInput,
+    /// Cursor position when the prompt was opened
+    pub(crate) origin: Option<usize>,
+    /// Entry of the command history shown in the prompt
+    pub(crate) recalled: Option<usize>,
+}
+
+/// Visual mode: the lines between the anchor and the cursor are selected, on
+/// top of whatever was selected when it started
+#[derive(Debug)]
+pub(crate) struct Visual {
+    /// Index (in `items`) of the line visual mode started on
+    pub(crate) anchor: usize,
+    /// Selection state of every line when visual mode started
+    pub(crate) before: Vec<bool>,
+}
+
+/// A line being typed in, in place in the list
+#[derive(Debug)]
+pub(crate) struct LineEdit {
+    pub(crate) input: TextInput,
+    pub(crate) target: EditTarget,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum EditTarget {
+    /// Changes the line at this index (in `items`)
+    Replace(usize),
+    /// Adds a new line at this index (in `items`), once submitted
+    Insert(usize),
+}
+
+/// A pending decision about how to save, after the file was found to have been
+/// changed on disk.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Conflict {
+    pub(crate) quit_after: bool,
+}
+
+/// Where lines that show up in the file while shfl is running are placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum NewLinesPosition {
+    /// At the start of the list
+    Top,
+    /// At the end of the list
+    Bottom,
+}
+
+/// What counts as a duplicate when removing duplicate lines; by default, only
+/// lines with the exact same bytes do.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct DedupeOptions {
+    pub(crate) ignore_case: bool,
+    /// Ignore whitespace at the start and end of lines
+    pub(crate) trim: bool,
 }
 
 #[derive(Debug)]
 pub(crate) struct Lines {
     pub(crate) items: Vec<LineItem>,
+    /// Tracks the cursor among the visible items (see [`Lines::visible`])
     pub(crate) state: ListState,
+    pub(crate) encoding: Encoding,
+    pub(crate) filter: Option<Filter>,
 }
 
+/// A line of the list. Its bytes and text are shared between clones, so the
+/// copies kept for undo only cost a pointer per line.
 #[derive(Debug, Clone)]
 pub(crate) struct LineItem {
-    pub(crate) content: String,
+    /// The line's bytes, exactly as they were read
+    pub(crate) raw: Rc<[u8]>,
+    /// The line break that ended the line in the file
+    pub(crate) ending: Option<LineEnding>,
+    /// The line decoded for display
+    pub(crate) content: Rc<str>,
     pub(crate) status: bool,
 }
 
@@ -52,6 +218,8 @@ pub(crate) enum RunningState {
 #[derive(Debug)]
 pub(crate) enum UserMessage {
     Success(String),
+    /// Outcomes that aren't failures, like a command that had nothing to do
+    Info(String),
     Error(String),
 }
 
@@ -59,6 +227,7 @@ impl UserMessage {
     pub(crate) fn value(&self) -> String {
         match self {
             UserMessage::Success(v) => v.clone(),
+            UserMessage::Info(v) => v.clone(),
             UserMessage::Error(v) => v.clone(),
         }
     }
@@ -66,24 +235,195 @@ impl UserMessage {
 
 impl From<&Vec<String>> for Lines {
     fn from(value: &Vec<String>) -> Self {
-        let items = value
+        let raw = value
             .iter()
-            .map(|line| LineItem::new(line, false))
+            .map(|line| RawLine::from(line.as_bytes().to_vec()))
+            .collect();
+        Self::new(raw, Encoding::Utf8)
+    }
+}
+
+impl Lines {
+    pub(crate) fn new(raw: Vec<RawLine>, encoding: Encoding) -> Self {
+        let items = raw
+            .into_iter()
+            .map(|line| LineItem::new(line, encoding, false))
             .collect();
         let state = ListState::default().with_selected(Some(0));
 
-        Self { items, state }
+        Self {
+            items,
+            state,
+            encoding,
+            filter: None,
+        }
+    }
+
+    pub(crate) fn selected_count(&self) -> usize {
+        self.items.iter().filter(|item| item.status).count()
+    }
+
+    /// Indices (in `items`) of the items shown in the list, which is all of
+    /// them unless a filter is applied.
+    pub(crate) fn visible(&self) -> Vec<usize> {
+        match &self.filter {
+            Some(filter) => self
+                .items
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| filter.matches(&item.content))
+                .map(|(i, _)| i)
+                .collect(),
+            None => (0..self.items.len()).collect(),
+        }
+    }
+
+    /// Index (in `items`) of the item under the cursor.
+    pub(crate) fn cursor(&self) -> Option<usize> {
+        let visible = self.visible();
+        let position = self.state.selected()?;
+
+        // the list state's index can run past the end until it's rendered
+        visible
+            .get(position.min(visible.len().checked_sub(1)?))
+            .copied()
+    }
+
+    /// Moves the cursor to the item at `index` (in `items`); if that item is
+    /// filtered out, the closest visible item after it is picked instead.
+    pub(crate) fn set_cursor(&mut self, index: usize) {
+        let visible = self.visible();
+        let position = visible.partition_point(|i| *i < index);
+
+        self.state
+            .select(Some(position.min(visible.len().saturating_sub(1))));
+    }
+
+    /// Index (in `items`) of the visible item right after/before the one at
+    /// `index`.
+    pub(crate) fn visible_neighbour(&self, index: usize, direction: Direction) -> Option<usize> {
+        let visible = self.visible();
+        match direction {
+            Direction::Forward => visible.into_iter().find(|i| *i > index),
+            Direction::Backward => visible.into_iter().rev().find(|i| *i < index),
+        }
+    }
+
+    /// Applies the changes made on disk since `base` was read, while keeping
+    /// the current order and the changes made here: lines that were removed
+    /// from the file are dropped, and ones that were added to it are placed at
+    /// `new_lines_at`. Lines are matched by their bytes. The cursor stays on
+    /// the same line, or moves to the next one if its line was dropped.
+    pub(crate) fn merge(
+        &mut self,
+        base: &[Rc<[u8]>],
+        incoming: &[RawLine],
+        new_lines_at: NewLinesPosition,
+    ) {
+        // positive counts were added on disk, negative ones were removed
+        let mut changes: HashMap<&[u8], isize> = HashMap::new();
+        incoming
+            .iter()
+            .for_each(|line| *changes.entry(line.bytes.as_slice()).or_default() += 1);
+        base.iter()
+            .for_each(|line| *changes.entry(line.as_ref()).or_default() -= 1);
+
+        let cursor = self.cursor();
+        let mut cursor_in_kept = None;
+        let mut kept: Vec<LineItem> = Vec::with_capacity(self.items.len());
+        for (i, item) in self.items.iter().enumerate() {
+            if cursor == Some(i) {
+                cursor_in_kept = Some(kept.len());
+            }
+            match changes.get_mut(item.raw.as_ref()) {
+                Some(count) if *count < 0 => *count += 1,
+                _ => kept.push(item.clone()),
+            }
+        }
+
+        let mut added: Vec<LineItem> = vec![];
+        for line in incoming {
+            if let Some(count) = changes.get_mut(line.bytes.as_slice())
+                && *count > 0
+            {
+                *count -= 1;
+                added.push(LineItem::new(line.clone(), self.encoding, false));
+            }
+        }
+
+        let added_before = match new_lines_at {
+            NewLinesPosition::Top => added.len(),
+            NewLinesPosition::Bottom => 0,
+        };
+        self.items = match new_lines_at {
+            NewLinesPosition::Top => added.into_iter().chain(kept).collect(),
+            NewLinesPosition::Bottom => kept.into_iter().chain(added).collect(),
+        };
+        if let Some(i) = cursor_in_kept {
+            self.set_cursor(added_before + i);
+        }
+    }
+
+    /// Removes lines that are duplicates of a line before them, and returns
+    /// how many were removed. The cursor stays on the same line, or moves to
+    /// the next one if its line was removed.
+    pub(crate) fn dedupe(&mut self, options: DedupeOptions) -> usize {
+        let cursor = self.cursor();
+        let mut seen = HashSet::new();
+        let mut removed = 0;
+        let mut removed_before_cursor = 0;
+
+        let items = std::mem::take(&mut self.items);
+        let mut kept = Vec::with_capacity(items.len());
+        for (i, item) in items.into_iter().enumerate() {
+            if seen.insert(item.dedupe_key(options).into_owned()) {
+                kept.push(item);
+                continue;
+            }
+            removed += 1;
+            if cursor.is_some_and(|c| i < c) {
+                removed_before_cursor += 1;
+            }
+        }
+
+        self.items = kept;
+        if let Some(c) = cursor {
+            self.set_cursor(c - removed_before_cursor);
+        }
+        removed
+    }
+
+    /// How many times each line shows up in the list, keyed by its bytes.
+    pub(crate) fn occurrences(&self) -> HashMap<&[u8], usize> {
+        let mut counts: HashMap<&[u8], usize> = HashMap::new();
+        self.items
+            .iter()
+            .for_each(|item| *counts.entry(item.raw.as_ref()).or_default() += 1);
+        counts
     }
 }
 
 impl LineItem {
-    fn new(line: &str, status: bool) -> Self {
+    pub(crate) fn new(line: RawLine, encoding: Encoding, status: bool) -> Self {
         Self {
-            content: line.to_string(),
+            content: encoding.decode(&line.bytes).into(),
+            raw: line.bytes.into(),
+            ending: line.ending,
             status,
         }
     }
 
+    pub(crate) fn as_line_ref(&self) -> LineRef<'_> {
+        (&self.raw, self.ending)
+    }
+
+    pub(crate) fn to_raw_line(&self) -> RawLine {
+        RawLine {
+            bytes: self.raw.to_vec(),
+            ending: self.ending,
+        }
+    }
+
     pub(crate) fn toggle(&mut self) -> bool {
         match self.status {
             true => {
@@ -96,17 +436,38 @@ impl LineItem {
             }
         }
     }
-}
 
-impl From<&LineItem> for ListItem<'_> {
-    fn from(value: &LineItem) -> Self {
-        let line = match value.status {
-            false => Line::from(value.content.clone()),
-            true => Line::styled(
-                format!("> {}", value.content.clone()),
-                Style::new().fg(SELECTED_COLOR),
-            ),
+    fn dedupe_key(&self, options: DedupeOptions) -> Cow<'_, [u8]> {
+        if options == DedupeOptions::default() {
+            return Cow::Borrowed(&self.raw);
+        }
+
+        // undecodable bytes all show up as the same replacement character, so
+        // such lines are compared by their bytes, folding only ASCII
+        if self.content.contains(char::REPLACEMENT_CHARACTER) && str::from_utf8(&self.raw).is_err()
+        {
+            let bytes = match options.trim {
+                true => self.raw.trim_ascii(),
+                false => &self.raw,
+            };
+            return match options.ignore_case {
+                true => Cow::Owned(bytes.to_ascii_lowercase()),
+                false => Cow::Borrowed(bytes),
+            };
+        }
+
+        let text = match options.trim {
+            true => self.content.trim(),
+            false => &self.content,
         };
-        ListItem::new(line)
+        match options.ignore_case {
+            true => Cow::Owned(text.to_lowercase().into_bytes()),
+            false => Cow::Borrowed(text.as_bytes()),
+        }
+    }
+
+    /// The parts of the line `spec` sorts on, one per key
+    pub(crate) fn sort_key<'a>(&'a self, spec: &KeySpec) -> Vec<&'a str> {
+        spec.extract(&self.content)
     }
 }
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+/// Lines that were cut or yanked, to be pasted elsewhere. Like in vim, the
+/// unnamed register always holds the last lines cut or yanked, and lines can
+/// also be stored in named registers (`a` to `z`); using an uppercase name
+/// appends to the register instead of replacing its contents.
+#[derive(Debug)]
+pub(crate) struct Registers<L> {
+    unnamed: Vec<L>,
+    named: HashMap<char, Vec<L>>,
+}
+
+impl<L> Default for Registers<L> {
+    fn default() -> Self {
+        Self {
+            unnamed: vec![],
+            named: HashMap::new(),
+        }
+    }
+}
+
+/// Registers are named `a` to `z`, or `A` to `Z` to append to them.
+pub(crate) fn is_valid_name(name: char) -> bool {
+    name.is_ascii_alphabetic()
+}
+
+impl<L: Clone> Registers<L> {
+    pub(crate) fn store(&mut self, name: Option<char>, lines: Vec<L>) {
+        if let Some(name) = name {
+            let register = self.named.entry(name.to_ascii_lowercase()).or_default();
+            match name.is_ascii_uppercase() {
+                true => register.extend(lines.iter().cloned()),
+                false => *register = lines.clone(),
+            }
+        }
+        self.unnamed = lines;
+    }
+
+    pub(crate) fn get(&self, name: Option<char>) -> &[L] {
+        match name {
+            Some(name) => self
+                .named
+                .get(&name.to_ascii_lowercase())
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+            None => &self.unnamed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uppercase_names_append_to_registers() {
+        // GIVEN
+        let mut registers: Registers<Vec<u8>> = Registers::default();
+
+        // WHEN
+        registers.store(Some('a'), vec![b"one".to_vec()]);
+        registers.store(Some('A'), vec![b"two".to_vec()]);
+        registers.store(None, vec![b"three".to_vec()]);
+
+        // THEN
+        assert_eq!(
+            registers.get(Some('a')),
+            &[b"one".to_vec(), b"two".to_vec()]
+        );
+        assert_eq!(registers.get(Some('A')), registers.get(Some('a')));
+        assert_eq!(registers.get(None), &[b"three".to_vec()]);
+        assert!(registers.get(Some('b')).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum Direction {
+    Forward,
+    Backward,
+}
+
+/// A search pattern along with the options it's matched with.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Search {
+    pub(crate) query: String,
+    pub(crate) regex: bool,
+    pub(crate) ignore_case: bool,
+    // None when the query is empty or isn't a valid regex
+    matcher: Option<Regex>,
+}
+
+impl Search {
+    pub(crate) fn new(query: &str, regex: bool, ignore_case: bool) -> Self {
+        let pattern = match regex {
+            true => query.to_string(),
+            false => regex::escape(query),
+        };
+        let matcher = match query.is_empty() {
+            true => None,
+            false => RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .ok(),
+        };
+
+        Self {
+            query: query.to_string(),
+            regex,
+            ignore_case,
+            matcher,
+        }
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        self.query.is_empty() || self.matcher.is_some()
+    }
+
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        self.matcher.as_ref().is_some_and(|m| m.is_match(text))
+    }
+
+    pub(crate) fn match_ranges(&self, text: &str) -> Vec<Range<usize>> {
+        match &self.matcher {
+            Some(m) => m
+                .find_iter(text)
+                .filter(|found| !found.is_empty())
+                .map(|found| found.range())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Finds the index of the next line that matches, starting at `from`
+    /// (inclusive) and wrapping around the ends of the list.
+    pub(crate) fn find(&self, lines: &[&str], from: usize, direction: Direction) -> Option<usize> {
+        let len = lines.len();
+        (0..len)
+            .map(|offset| match direction {
+                Direction::Forward => (from + offset) % len,
+                Direction::Backward => (from + len - offset) % len,
+            })
+            .find(|i| self.is_match(lines[*i]))
+    }
+}
+
+/// Narrows the list down to lines that match a query, either fuzzily or as a
+/// regex.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Filter {
+    pub(crate) query: String,
+    pub(crate) regex: bool,
+    // None when not in regex mode, or when the query isn't a valid regex
+    matcher: Option<Regex>,
+}
+
+impl Filter {
+    pub(crate) fn new(query: &str, regex: bool) -> Self {
+        let matcher = match regex {
+            true => Regex::new(query).ok(),
+            false => None,
+        };
+
+        Self {
+            query: query.to_string(),
+            regex,
+            matcher,
+        }
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        !self.regex || self.matcher.is_some()
+    }
+
+    /// Whether a line is kept; an empty or invalid query keeps every line.
+    pub(crate) fn matches(&self, text: &str) -> bool {
+        if self.query.is_empty() {
+            return true;
+        }
+
+        match (&self.matcher, self.regex) {
+            (Some(m), _) => m.is_match(text),
+            (None, true) => true,
+            (None, false) => fuzzy_match(&self.query, text),
+        }
+    }
+}
+
+// the query's chars have to show up in order, though not necessarily next to
+// each other; matching ignores case unless the query has uppercase chars in it
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let mut text_chars = text.chars();
+
+    query.chars().all(|q| {
+        text_chars.any(|t| match ignore_case {
+            true => t.to_lowercase().eq(q.to_lowercase()),
+            false => t == q,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_escapes_query_unless_in_regex_mode() {
+        // GIVEN
+        let plain = Search::new("a.c", false, false);
+        let regex = Search::new("a.c", true, false);
+
+        // WHEN
+        let plain_matches = [plain.is_match("xa.cx"), plain.is_match("abc")];
+        let regex_matches = regex.is_match("abc");
+
+        // THEN
+        assert_eq!(plain_matches, [true, false]);
+        assert!(regex_matches);
+    }
+
+    #[test]
+    fn search_can_ignore_case() {
+        // GIVEN
+        let search = Search::new("main", false, true);
+
+        // WHEN
+        let ranges = search.match_ranges("Main / MAIN");
+
+        // THEN
+        assert_eq!(ranges, vec![0..4, 7..11]);
+    }
+
+    #[test]
+    fn invalid_regex_matches_nothing() {
+        // GIVEN
+        let search = Search::new("(unclosed", true, false);
+
+        // WHEN
+        let matches = search.is_match("(unclosed");
+
+        // THEN
+        assert!(!search.is_valid());
+        assert!(!matches);
+    }
+
+    #[test]
+    fn find_wraps_around_in_both_directions() {
+        // GIVEN
+        let lines = ["feat", "fix", "chore", "feat"];
+        let search = Search::new("feat", false, false);
+
+        // WHEN
+        let forward: Vec<_> = [1, 3]
+            .map(|from| search.find(&lines, from, Direction::Forward))
+            .to_vec();
+        let backward: Vec<_> = [2, 1, 0]
+            .map(|from| search.find(&lines, from, Direction::Backward))
+            .to_vec();
+
+        // THEN
+        assert_eq!(forward, vec![Some(3), Some(3)]);
+        assert_eq!(backward, vec![Some(0), Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn fuzzy_filter_matches_chars_in_order_with_smart_case() {
+        // GIVEN
+        let lower = Filter::new("wrk", false);
+        let upper = Filter::new("Wrk", false);
+
+        // WHEN
+        let lower_matches = ["work/api", "Work/api", "rwk"].map(|l| lower.matches(l));
+        let upper_matches = ["Work/api", "work/api"].map(|l| upper.matches(l));
+
+        // THEN
+        assert_eq!(lower_matches, [true, true, false]);
+        assert_eq!(upper_matches, [true, false]);
+    }
+
+    #[test]
+    fn regex_filter_keeps_everything_while_invalid() {
+        // GIVEN
+        let valid = Filter::new("^w.*i$", true);
+        let invalid = Filter::new("(w", true);
+
+        // WHEN
+        let valid_matches = ["work/api", "personal"].map(|l| valid.matches(l));
+        let invalid_matches = invalid.matches("personal");
+
+        // THEN
+        assert_eq!(valid_matches, [true, false]);
+        assert!(!invalid.is_valid());
+        assert!(invalid_matches);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::model::LineItem;
+use crate::sort::rearrange;
+use std::hash::{BuildHasher, Hasher};
+
+/// A small, seedable pseudo-random number generator (SplitMix64). The same
+/// seed always produces the same sequence, on every platform, which is what
+/// makes shuffles reproducible.
+#[derive(Debug)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // a number in 0..bound, without modulo bias
+    fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - (u64::MAX % bound);
+        loop {
+            let n = self.next_u64();
+            if n < zone {
+                return n % bound;
+            }
+        }
+    }
+}
+
+/// A seed that's different on every call.
+pub(crate) fn random_seed() -> u64 {
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+/// Randomly permutes the items at `indices` among themselves (Fisher-Yates);
+/// items at other indices stay where they are. Returns the index each item at
+/// `indices` came from.
+pub(crate) fn shuffle_in_place(items: &mut [LineItem], indices: &[usize], seed: u64) -> Vec<usize> {
+    let mut rng = Rng::new(seed);
+    let mut order = indices.to_vec();
+    for i in (1..order.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        order.swap(i, j);
+    }
+
+    rearrange(items, indices, &order);
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Lines;
+
+    fn shuffled(seed: u64) -> Vec<String> {
+        let lines: Vec<String> = (0..10).map(|n| n.to_string()).collect();
+        let mut items = Lines::from(&lines).items;
+        let indices: Vec<usize> = (0..items.len()).collect();
+        shuffle_in_place(&mut items, &indices, seed);
+        items.into_iter().map(|i| i.content.to_string()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_order() {
+        // GIVEN
+        let seed = 42;
+
+        // WHEN
+        let first = shuffled(seed);
+        let second = shuffled(seed);
+        let other = shuffled(seed + 1);
+
+        // THEN
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn shuffling_keeps_every_line() {
+        // GIVEN
+        let seed = 7;
+
+        // WHEN
+        let mut result = shuffled(seed);
+
+        // THEN
+        result.sort_by_key(|l| l.parse::<usize>().unwrap());
+        let expected: Vec<String> = (0..10).map(|n| n.to_string()).collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn rng_output_is_stable_across_releases() {
+        // GIVEN
+        let mut rng = Rng::new(0);
+
+        // WHEN
+        let first = rng.next_u64();
+
+        // THEN
+        // reference output of SplitMix64 for seed 0; changing it would break
+        // reproducing shuffles from seeds users have noted down
+        assert_eq!(first, 0xe220a8397b1dcdaf);
+    }
+}
@@ -0,0 +1,481 @@
+use crate::model::LineItem;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum SortKind {
+    /// Alphabetical, comparing chars as they are
+    Lexical,
+    /// Alphabetical, ignoring case
+    IgnoreCase,
+    /// Alphabetical, but with runs of digits compared as numbers ("v1.9"
+    /// comes before "v1.10")
+    Natural,
+    /// By the number each line starts with
+    Numeric,
+    /// By the number of chars in each line
+    Length,
+    /// Reverses the current order
+    Reverse,
+}
+
+impl SortKind {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            SortKind::Lexical => "alphabetically",
+            SortKind::IgnoreCase => "alphabetically, ignoring case",
+            SortKind::Natural => "naturally",
+            SortKind::Numeric => "numerically",
+            SortKind::Length => "by length",
+            SortKind::Reverse => "in reverse",
+        }
+    }
+}
+
+/// Sorts the items at `indices` among themselves; items at other indices stay
+/// where they are. The sort is stable. Returns the index each item at
+/// `indices` came from.
+pub(crate) fn sort_in_place(
+    items: &mut [LineItem],
+    indices: &[usize],
+    kind: SortKind,
+) -> Vec<usize> {
+    let mut order = indices.to_vec();
+    let content = |i: &usize| items[*i].content.as_ref();
+
+    match kind {
+        SortKind::Lexical => order.sort_by(|a, b| content(a).cmp(content(b))),
+        SortKind::IgnoreCase => order.sort_by_cached_key(|i| content(i).to_lowercase()),
+        SortKind::Natural => order.sort_by(|a, b| natural_cmp(content(a), content(b))),
+        SortKind::Numeric => order.sort_by(|a, b| numeric_cmp(content(a), content(b))),
+        SortKind::Length => order.sort_by_key(|i| content(i).chars().count()),
+        SortKind::Reverse => order.reverse(),
+    }
+
+    rearrange(items, indices, &order);
+    order
+}
+
+/// What to sort lines on, parsed from a spec like `sort -k` takes:
+/// `[-t DELIM] [-e REGEX] KEY...`, where each key is a field number (starting
+/// at 1) followed by any of `n` (numeric), `v` (natural), `i` (ignore case)
+/// and `r` (descending). For example, `-t , 3nr 1` sorts by the third
+/// comma-separated column numerically in descending order, then by the first.
+/// With `-e`, field numbers refer to the regex's capture groups.
+#[derive(Debug)]
+pub(crate) struct KeySpec {
+    pub(crate) fields: FieldSource,
+    pub(crate) keys: Vec<SortKey>,
+}
+
+/// How a line is split into the fields keys refer to
+#[derive(Debug)]
+pub(crate) enum FieldSource {
+    /// Runs of whitespace separate fields
+    Whitespace,
+    Delimiter(String),
+    /// Capture groups are fields; without any groups, the whole match is
+    /// field 1
+    Regex(Regex),
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct SortKey {
+    /// 1-based
+    pub(crate) field: usize,
+    pub(crate) compare: KeyCompare,
+    pub(crate) descending: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum KeyCompare {
+    Text,
+    IgnoreCase,
+    Natural,
+    Numeric,
+}
+
+impl KeySpec {
+    pub(crate) fn parse(spec: &str) -> Result<Self, String> {
+        let mut fields = FieldSource::Whitespace;
+        let mut keys = vec![];
+        let mut tokens = tokenize(spec)?.into_iter();
+
+        while let Some(token) = tokens.next() {
+            match token.as_str() {
+                "-t" => {
+                    let delimiter = tokens.next().ok_or("-t needs a delimiter")?;
+                    fields = match delimiter.as_str() {
+                        "" => return Err("delimiter can't be empty".to_string()),
+                        "\\t" => FieldSource::Delimiter("\t".to_string()),
+                        _ => FieldSource::Delimiter(delimiter),
+                    };
+                }
+                "-e" => {
+                    let pattern = tokens.next().ok_or("-e needs a regex")?;
+                    let regex =
+                        Regex::new(&pattern).map_err(|_| format!("invalid regex: {pattern}"))?;
+                    fields = FieldSource::Regex(regex);
+                }
+                _ => keys.push(SortKey::parse(&token)?),
+            }
+        }
+
+        if keys.is_empty() {
+            keys.push(SortKey {
+                field: 1,
+                compare: KeyCompare::Text,
+                descending: false,
+            });
+        }
+
+        Ok(Self { fields, keys })
+    }
+
+    /// The parts of `text` that each key sorts on, in the order of the keys;
+    /// fields a line doesn't have are empty.
+    pub(crate) fn extract<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let fields: Vec<&str> = match &self.fields {
+            FieldSource::Whitespace => text.split_whitespace().collect(),
+            FieldSource::Delimiter(d) => text.split(d.as_str()).collect(),
+            FieldSource::Regex(regex) => match regex.captures(text) {
+                Some(captures) if captures.len() > 1 => captures
+                    .iter()
+                    .skip(1)
+                    .map(|group| group.map(|m| m.as_str()).unwrap_or_default())
+                    .collect(),
+                Some(captures) => vec![captures.get(0).map(|m| m.as_str()).unwrap_or_default()],
+                None => vec![],
+            },
+        };
+
+        self.keys
+            .iter()
+            .map(|key| fields.get(key.field - 1).copied().unwrap_or_default())
+            .collect()
+    }
+
+    /// Compares the values [`KeySpec::extract`] returned for two lines.
+    pub(crate) fn compare(&self, a: &[&str], b: &[&str]) -> Ordering {
+        self.keys
+            .iter()
+            .zip(a.iter().zip(b.iter()))
+            .map(|(key, (x, y))| {
+                let ordering = match key.compare {
+                    KeyCompare::Text => x.cmp(y),
+                    KeyCompare::IgnoreCase => x.to_lowercase().cmp(&y.to_lowercase()),
+                    KeyCompare::Natural => natural_cmp(x, y),
+                    KeyCompare::Numeric => numeric_cmp(x, y),
+                };
+                match key.descending {
+                    true => ordering.reverse(),
+                    false => ordering,
+                }
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl SortKey {
+    fn parse(token: &str) -> Result<Self, String> {
+        let digits = token.chars().take_while(char::is_ascii_digit).count();
+        let field = match token[..digits].parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(format!("invalid key: {token}")),
+        };
+
+        let mut key = Self {
+            field,
+            compare: KeyCompare::Text,
+            descending: false,
+        };
+        for flag in token[digits..].chars() {
+            match flag {
+                'n' => key.compare = KeyCompare::Numeric,
+                'v' => key.compare = KeyCompare::Natural,
+                'i' => key.compare = KeyCompare::IgnoreCase,
+                'r' => key.descending = true,
+                _ => return Err(format!("unknown flag '{flag}' in key: {token}")),
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+/// Sorts the items at `indices` among themselves by `spec`, the same way
+/// [`sort_in_place`] does.
+pub(crate) fn sort_by_key_spec(
+    items: &mut [LineItem],
+    indices: &[usize],
+    spec: &KeySpec,
+) -> Vec<usize> {
+    let mut keyed: Vec<(usize, Vec<&str>)> = indices
+        .iter()
+        .map(|i| (*i, items[*i].sort_key(spec)))
+        .collect();
+    keyed.sort_by(|(_, a), (_, b)| spec.compare(a, b));
+
+    let order: Vec<usize> = keyed.into_iter().map(|(i, _)| i).collect();
+    rearrange(items, indices, &order);
+    order
+}
+
+// splits on whitespace, except inside single or double quotes
+fn tokenize(spec: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+
+    for c in spec.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_default().push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => tokens.extend(current.take()),
+            (None, c) => current.get_or_insert_default().push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unclosed quote".to_string());
+    }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+/// Sorts the items at `indices` among themselves by keys computed for each
+/// line elsewhere (looked up by the line's raw bytes), compared naturally.
+/// Lines without a key come first.
+pub(crate) fn sort_by_computed_keys(
+    items: &mut [LineItem],
+    indices: &[usize],
+    keys: &HashMap<Vec<u8>, String>,
+) -> Vec<usize> {
+    let key = |i: &usize| {
+        keys.get(items[*i].raw.as_ref())
+            .map(String::as_str)
+            .unwrap_or_default()
+    };
+    let mut order = indices.to_vec();
+    order.sort_by(|a, b| natural_cmp(key(a), key(b)));
+
+    rearrange(items, indices, &order);
+    order
+}
+
+/// Puts the items at `order` into the slots at `indices`, in that order.
+pub(crate) fn rearrange(items: &mut [LineItem], indices: &[usize], order: &[usize]) {
+    let moved: Vec<LineItem> = order.iter().map(|i| items[*i].clone()).collect();
+    indices
+        .iter()
+        .zip(moved)
+        .for_each(|(i, item)| items[*i] = item);
+}
+
+/// Compares strings chunk by chunk, where runs of digits are compared by their
+/// numeric value and everything else is compared as is.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chunks = chunks(a);
+    let mut b_chunks = chunks(b);
+
+    loop {
+        match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let ordering = match (is_digits(x), is_digits(y)) {
+                    (true, true) => cmp_digits(x, y),
+                    _ => x.cmp(y),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+/// Compares strings by the number they start with (ignoring leading
+/// whitespace). Lines that don't start with a number come first.
+pub(crate) fn numeric_cmp(a: &str, b: &str) -> Ordering {
+    match (leading_number(a), leading_number(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+pub(crate) fn leading_number(text: &str) -> Option<f64> {
+    let text = text.trim_start();
+    let mut end = 0;
+    let mut seen_digit = false;
+    let mut seen_point = false;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '-' | '+' if i == 0 => {}
+            '0'..='9' => seen_digit = true,
+            '.' if !seen_point => seen_point = true,
+            _ => break,
+        }
+        end = i + c.len_utf8();
+    }
+
+    match seen_digit {
+        true => text[..end].trim_end_matches('.').parse().ok(),
+        false => None,
+    }
+}
+
+fn chunks(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let digits = first.is_ascii_digit();
+        let end = rest
+            .char_indices()
+            .find(|(_, c)| c.is_ascii_digit() != digits)
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        let (chunk, remaining) = rest.split_at(end);
+        rest = remaining;
+        Some(chunk)
+    })
+}
+
+fn is_digits(chunk: &str) -> bool {
+    chunk.starts_with(|c: char| c.is_ascii_digit())
+}
+
+// compares digit runs of any length without parsing them
+fn cmp_digits(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_compares_digit_runs_as_numbers() {
+        // GIVEN
+        let mut versions = vec!["v1.10", "v1.9", "v1.09.1", "v2", "v1.9.1", "v10", "v1"];
+
+        // WHEN
+        versions.sort_by(|a, b| natural_cmp(a, b));
+
+        // THEN
+        assert_eq!(
+            versions,
+            vec!["v1", "v1.9", "v1.09.1", "v1.9.1", "v1.10", "v2", "v10"]
+        );
+    }
+
+    #[test]
+    fn leading_number_parses_numbers_at_the_start() {
+        // GIVEN
+        let lines = ["  42 apples", "-3.5kg", "7.", "apples 42", "-"];
+
+        // WHEN
+        let numbers = lines.map(leading_number);
+
+        // THEN
+        assert_eq!(numbers, [Some(42.0), Some(-3.5), Some(7.0), None, None]);
+    }
+
+    #[test]
+    fn numeric_cmp_puts_lines_without_numbers_first() {
+        // GIVEN
+        let mut lines = vec!["10 b", "9 a", "none", "-1 c", "100"];
+
+        // WHEN
+        lines.sort_by(|a, b| numeric_cmp(a, b));
+
+        // THEN
+        assert_eq!(lines, vec!["none", "-1 c", "9 a", "10 b", "100"]);
+    }
+
+    #[test]
+    fn key_spec_parses_delimiter_and_keys() {
+        // GIVEN
+        let text = "-t ',' 3nr 1";
+
+        // WHEN
+        let spec = KeySpec::parse(text).expect("spec should've been parsed");
+
+        // THEN
+        assert!(matches!(&spec.fields, FieldSource::Delimiter(d) if d == ","));
+        assert_eq!(
+            spec.keys,
+            vec![
+                SortKey {
+                    field: 3,
+                    compare: KeyCompare::Numeric,
+                    descending: true,
+                },
+                SortKey {
+                    field: 1,
+                    compare: KeyCompare::Text,
+                    descending: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn key_spec_reports_invalid_input() {
+        // GIVEN
+        let specs = ["0", "2x", "-t", "-e '(unclosed'", "-e 'unclosed"];
+
+        // WHEN
+        let rejected = specs.map(|spec| KeySpec::parse(spec).is_err());
+
+        // THEN
+        assert_eq!(rejected, [true; 5]);
+    }
+
+    #[test]
+    fn sorting_by_columns_breaks_ties_with_later_keys() {
+        // GIVEN
+        let lines: Vec<String> = ["bob,ops,3", "amy,dev,10", "cat,ops,10", "dan,dev,2"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut items = crate::model::Lines::from(&lines).items;
+        let spec = KeySpec::parse("-t , 3nr 1").expect("spec should've been parsed");
+
+        // WHEN
+        sort_by_key_spec(&mut items, &[0, 1, 2, 3], &spec);
+
+        // THEN
+        let content: Vec<&str> = items.iter().map(|i| i.content.as_ref()).collect();
+        assert_eq!(
+            content,
+            vec!["amy,dev,10", "cat,ops,10", "bob,ops,3", "dan,dev,2"]
+        );
+    }
+
+    #[test]
+    fn sorting_by_regex_uses_capture_groups() {
+        // GIVEN
+        let spec = KeySpec::parse(r#"-e "took (\d+)ms" 1n"#).expect("spec should've been parsed");
+
+        // WHEN
+        let matched = spec.extract("GET /api took 120ms");
+        let unmatched = spec.extract("starting up");
+
+        // THEN
+        assert_eq!(matched, vec!["120"]);
+        assert_eq!(unmatched, vec![""]);
+    }
+}
@@ -0,0 +1,40 @@
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use ratatui::{Terminal, backend::CrosstermBackend};
+use std::fs::File;
+use std::io;
+
+const TTY_PATH: &str = "/dev/tty";
+
+pub(crate) type TtyTerminal = Terminal<CrosstermBackend<File>>;
+
+/// Initializes a terminal that draws on the controlling tty instead of stdout,
+/// so that stdout stays free for the output of pipe mode.
+pub(crate) fn init_tty() -> io::Result<TtyTerminal> {
+    let mut tty = open_tty()?;
+    enable_raw_mode()?;
+    execute!(tty, EnterAlternateScreen)?;
+    set_panic_hook();
+
+    Terminal::new(CrosstermBackend::new(tty))
+}
+
+pub(crate) fn restore_tty() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(open_tty()?, LeaveAlternateScreen)?;
+    Ok(())
+}
+
+fn open_tty() -> io::Result<File> {
+    File::options().read(true).write(true).open(TTY_PATH)
+}
+
+fn set_panic_hook() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_tty();
+        hook(info);
+    }));
+}
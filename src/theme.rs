@@ -0,0 +1,267 @@
+use ratatui::style::{Color, Style};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Named sets of colors; without one in the config, the terminal's background
+/// decides between dark and light.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ThemeName {
+    Dark,
+    Light,
+    HighContrast,
+}
+
+/// Colors the list and the title bar are drawn with. In monochrome mode
+/// (when `NO_COLOR` is set) colors are ignored, and bold/reversed text stands
+/// in for them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Theme {
+    pub(crate) text: Color,
+    pub(crate) primary: Color,
+    pub(crate) title: Color,
+    pub(crate) selected: Color,
+    pub(crate) search_match: Color,
+    pub(crate) duplicate: Color,
+    pub(crate) monochrome: bool,
+}
+
+/// Colors given in the config, each replacing the one of the theme.
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ColorOverrides {
+    text: Option<String>,
+    primary: Option<String>,
+    title: Option<String>,
+    selected: Option<String>,
+    #[serde(rename = "match")]
+    search_match: Option<String>,
+    duplicate: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::named(ThemeName::Dark)
+    }
+}
+
+impl Theme {
+    pub(crate) fn named(name: ThemeName) -> Self {
+        let (text, primary, title, selected, search_match, duplicate) = match name {
+            ThemeName::Dark => (
+                Color::White,
+                Color::from_u32(0xd3869b),
+                Color::from_u32(0x282828),
+                Color::from_u32(0x83a598),
+                Color::from_u32(0xfabd2f),
+                Color::from_u32(0xfe8019),
+            ),
+            ThemeName::Light => (
+                Color::Black,
+                Color::from_u32(0x8f3f71),
+                Color::from_u32(0xfbf1c7),
+                Color::from_u32(0x076678),
+                Color::from_u32(0xb57614),
+                Color::from_u32(0xaf3a03),
+            ),
+            ThemeName::HighContrast => (
+                Color::White,
+                Color::Yellow,
+                Color::Black,
+                Color::LightCyan,
+                Color::LightYellow,
+                Color::LightRed,
+            ),
+        };
+
+        Self {
+            text,
+            primary,
+            title,
+            selected,
+            search_match,
+            duplicate,
+            monochrome: false,
+        }
+    }
+
+    /// Replaces the colors given, reporting every one that can't be parsed,
+    /// one per line.
+    pub(crate) fn with_overrides(mut self, overrides: &ColorOverrides) -> Result<Self, String> {
+        let mut errors = vec![];
+        let fields = [
+            ("text", &overrides.text, &mut self.text),
+            ("primary", &overrides.primary, &mut self.primary),
+            ("title", &overrides.title, &mut self.title),
+            ("selected", &overrides.selected, &mut self.selected),
+            ("match", &overrides.search_match, &mut self.search_match),
+            ("duplicate", &overrides.duplicate, &mut self.duplicate),
+        ];
+        for (name, value, color) in fields {
+            let Some(value) = value else {
+                continue;
+            };
+            match Color::from_str(value) {
+                Ok(parsed) => *color = parsed,
+                Err(_) => errors.push(format!("colors.{name}: \"{value}\" isn't a color")),
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(self),
+            false => Err(errors.join("\n")),
+        }
+    }
+
+    pub(crate) fn text_style(&self) -> Style {
+        match self.monochrome {
+            true => Style::new(),
+            false => Style::new().fg(self.text),
+        }
+    }
+
+    /// The current line, and text being edited
+    pub(crate) fn cursor_style(&self) -> Style {
+        match self.monochrome {
+            true => Style::new().bold(),
+            false => Style::new().fg(self.primary),
+        }
+    }
+
+    pub(crate) fn title_style(&self) -> Style {
+        match self.monochrome {
+            true => Style::new().bold().reversed(),
+            false => Style::new().bold().bg(self.primary).fg(self.title),
+        }
+    }
+
+    pub(crate) fn selected_style(&self) -> Style {
+        match self.monochrome {
+            true => Style::new().bold(),
+            false => Style::new().fg(self.selected),
+        }
+    }
+
+    pub(crate) fn match_style(&self) -> Style {
+        match self.monochrome {
+            true => Style::new().reversed(),
+            false => Style::new().bg(self.search_match).fg(self.title),
+        }
+    }
+
+    pub(crate) fn duplicate_style(&self) -> Style {
+        match self.monochrome {
+            true => Style::new().bold(),
+            false => Style::new().fg(self.duplicate),
+        }
+    }
+}
+
+/// Guesses the theme from `COLORFGBG` ("foreground;background", as set by
+/// rxvt, konsole and others), where the background is one of the 16 terminal
+/// colors.
+pub(crate) fn theme_for_background(colorfgbg: &str) -> Option<ThemeName> {
+    let background: u8 = colorfgbg.rsplit(';').next()?.parse().ok()?;
+    match background {
+        7 | 9..=15 => Some(ThemeName::Light),
+        0..=6 | 8 => Some(ThemeName::Dark),
+        _ => None,
+    }
+}
+
+/// See https://no-color.org
+pub(crate) fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_background_color_picks_the_theme() {
+        // GIVEN
+        let values = ["15;0", "0;15", "0;default;7", "15;default", ""];
+
+        // WHEN
+        let themes = values.map(theme_for_background);
+
+        // THEN
+        assert_eq!(
+            themes,
+            [
+                Some(ThemeName::Dark),
+                Some(ThemeName::Light),
+                Some(ThemeName::Light),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn overridden_colors_replace_the_themes() {
+        // GIVEN
+        let overrides = ColorOverrides {
+            primary: Some("#ff0000".to_string()),
+            search_match: Some("blue".to_string()),
+            ..Default::default()
+        };
+
+        // WHEN
+        let theme = Theme::named(ThemeName::Light)
+            .with_overrides(&overrides)
+            .expect("colors should be valid");
+
+        // THEN
+        assert_eq!(theme.primary, Color::Rgb(255, 0, 0));
+        assert_eq!(theme.search_match, Color::Blue);
+        assert_eq!(theme.text, Theme::named(ThemeName::Light).text);
+    }
+
+    #[test]
+    fn every_invalid_color_is_reported() {
+        // GIVEN
+        let overrides = ColorOverrides {
+            text: Some("blurple".to_string()),
+            duplicate: Some("#12".to_string()),
+            selected: Some("cyan".to_string()),
+            ..Default::default()
+        };
+
+        // WHEN
+        let errors = Theme::default().with_overrides(&overrides).unwrap_err();
+
+        // THEN
+        let errors: Vec<&str> = errors.lines().collect();
+        assert_eq!(
+            errors,
+            vec![
+                "colors.text: \"blurple\" isn't a color",
+                "colors.duplicate: \"#12\" isn't a color",
+            ]
+        );
+    }
+
+    #[test]
+    fn monochrome_mode_uses_no_colors() {
+        // GIVEN
+        let theme = Theme {
+            monochrome: true,
+            ..Theme::default()
+        };
+
+        // WHEN
+        let styles = [
+            theme.text_style(),
+            theme.cursor_style(),
+            theme.title_style(),
+            theme.selected_style(),
+            theme.match_style(),
+            theme.duplicate_style(),
+        ];
+
+        // THEN
+        assert!(styles.iter().all(|s| s.fg.is_none() && s.bg.is_none()));
+    }
+}
@@ -1,11 +1,52 @@
-use crate::common::View;
-use crate::message::Message;
-use crate::model::{LineItem, Model, RunningState, UserMessage};
-use crate::utils::write_to_file;
-use ratatui::crossterm::event::{self, Event, KeyCode};
-use std::time::Duration;
-
-pub(crate) fn handle_event(model: &Model) -> anyhow::Result<Option<Message>> {
+use crate::command::{complete, parse_command};
+use crate::common::{MESSAGE_TIMEOUT, Menu, PromptKind, View};
+use crate::external::{KeyJob, KeyResult};
+use crate::history::Snapshot;
+use crate::input::{InputAction, TextInput};
+use crate::keymap::{KeyChord, Lookup, action_message};
+use crate::message::{ConflictResolution, EditKind, Message};
+use crate::model::{
+    Conflict, DedupeOptions, EditTarget, LineEdit, LineItem, Lines, Model, Prompt, RunningState,
+    Target, UserMessage, Visual,
+};
+use crate::register::is_valid_name;
+use crate::search::{Direction, Filter, Search};
+use crate::shuffle::{random_seed, shuffle_in_place};
+use crate::sort::{KeySpec, SortKind, sort_by_computed_keys, sort_by_key_spec, sort_in_place};
+use crate::utils::{
+    LineRef, RawLine, fingerprint_file, read_lines_with_fingerprint, write_to_file, write_to_path,
+};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use std::collections::HashSet;
+use std::fs;
+use std::time::{Duration, Instant};
+
+pub(crate) fn handle_event(model: &mut Model) -> anyhow::Result<Option<Message>> {
+    if model
+        .message_expires_at
+        .is_some_and(|expires_at| expires_at <= Instant::now())
+    {
+        model.message = None;
+        model.message_expires_at = None;
+    }
+
+    if model.reload_pending && model.edit.is_none() && model.prompt.is_none() {
+        return Ok(Some(Message::FileChangedOnDisk));
+    }
+
+    if let Some(watcher) = model.watcher.as_mut()
+        && watcher.poll()
+    {
+        return Ok(Some(Message::FileChangedOnDisk));
+    }
+
+    if let Some(job) = model.key_job.as_ref() {
+        let results = job.poll();
+        if !results.is_empty() {
+            return Ok(Some(Message::SortKeysComputed(results)));
+        }
+    }
+
     if event::poll(Duration::from_millis(16))?
         && let Event::Key(key) = event::read()?
         && key.kind == event::KeyEventKind::Press
@@ -16,110 +57,444 @@ pub(crate) fn handle_event(model: &Model) -> anyhow::Result<Option<Message>> {
 }
 
 fn handle_key(model: &Model, key: event::KeyEvent) -> Option<Message> {
-    match model.view {
-        View::List => match key.code {
-            KeyCode::Char('1') => Some(Message::MoveToIndex(0)),
-            KeyCode::Char('2') => Some(Message::MoveToIndex(1)),
-            KeyCode::Char('3') => Some(Message::MoveToIndex(2)),
-            KeyCode::Char('4') => Some(Message::MoveToIndex(3)),
-            KeyCode::Char('5') => Some(Message::MoveToIndex(4)),
-            KeyCode::Char('6') => Some(Message::MoveToIndex(5)),
-            KeyCode::Char('7') => Some(Message::MoveToIndex(6)),
-            KeyCode::Char('8') => Some(Message::MoveToIndex(7)),
-            KeyCode::Char('9') => Some(Message::MoveToIndex(8)),
-            KeyCode::Char('j') | KeyCode::Down => Some(Message::GoToNextItem),
-            KeyCode::Char('k') | KeyCode::Up => Some(Message::GoToPreviousPreview),
-            KeyCode::Char('g') => Some(Message::GoToFirstItem),
-            KeyCode::Char('G') => Some(Message::GoToLastItem),
-            KeyCode::Char('J') => Some(Message::SwitchWithNextItem),
-            KeyCode::Char('K') => Some(Message::SwitchWithPreviousItem),
-            KeyCode::Enter => Some(Message::MoveToTop),
-            KeyCode::Char('s') | KeyCode::Char(' ') => Some(Message::ToggleSelection),
-            KeyCode::Esc | KeyCode::Char('q') => Some(Message::Quit),
-            KeyCode::Char('?') => Some(Message::ShowView(View::Help)),
-            KeyCode::Char('w') => Some(Message::SaveSelection),
+    if model.key_job.is_some() {
+        return match key.code {
+            KeyCode::Esc => Some(Message::CancelSortKeys),
             _ => None,
-        },
+        };
+    }
+
+    if model.conflict.is_some() {
+        return match key.code {
+            KeyCode::Char('o') => Some(Message::ResolveConflict(ConflictResolution::Overwrite)),
+            KeyCode::Char('r') => Some(Message::ResolveConflict(ConflictResolution::Reload)),
+            KeyCode::Char('m') => Some(Message::ResolveConflict(ConflictResolution::Merge)),
+            KeyCode::Esc | KeyCode::Char('q') => {
+                Some(Message::ResolveConflict(ConflictResolution::Cancel))
+            }
+            _ => None,
+        };
+    }
+
+    if let Some(prompt) = &model.prompt {
+        return handle_prompt_key(prompt, key);
+    }
+
+    if model.edit.is_some() {
+        return match key.code {
+            KeyCode::Esc => Some(Message::CancelEdit),
+            KeyCode::Enter => Some(Message::SubmitEdit),
+            _ => input_action(key).map(Message::EditLine),
+        };
+    }
+
+    if let Some(menu) = model.menu {
+        return Some(handle_menu_key(menu, key));
+    }
+
+    match model.view {
+        View::List => handle_list_key(model, key),
         View::Help => match key.code {
             KeyCode::Esc | KeyCode::Char('q') => Some(Message::Quit),
-            KeyCode::Char('?') => Some(Message::ShowView(View::Help)),
-            _ => None,
+            _ => match model.keymap.lookup(&[KeyChord::from(key)]) {
+                Lookup::Action("help") => Some(Message::ShowView(View::Help)),
+                _ => None,
+            },
         },
     }
 }
 
+// digits that aren't bound to anything make up a count
+fn handle_list_key(model: &Model, key: event::KeyEvent) -> Option<Message> {
+    let chord = KeyChord::from(key);
+    let mut keys = model.partial_keys.clone();
+    keys.push(chord);
+
+    let action = match model.keymap.lookup(&keys) {
+        Lookup::Action(action) => action,
+        Lookup::Prefix => return Some(Message::PendingKey(chord)),
+        Lookup::Unbound if !model.partial_keys.is_empty() => {
+            return Some(Message::ClearPendingKeys);
+        }
+        Lookup::Unbound => {
+            return match key.code {
+                // a leading 0 isn't part of a count
+                KeyCode::Char(c @ '0'..='9') if c != '0' || model.count.is_some() => {
+                    c.to_digit(10).map(|d| Message::AppendToCount(d as usize))
+                }
+                _ => None,
+            };
+        }
+    };
+
+    // actions are checked when the keymap is loaded
+    let message = action_message(action).ok()?;
+    match (message, model.count) {
+        // positions typed in are 1-based
+        (Message::MoveToTop, Some(position)) => {
+            Some(Message::MoveToIndex(position.saturating_sub(1)))
+        }
+        (message, _) => Some(message),
+    }
+}
+
+fn handle_prompt_key(prompt: &Prompt, key: event::KeyEvent) -> Option<Message> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        match (prompt.kind, key.code) {
+            (PromptKind::Search | PromptKind::Filter, KeyCode::Char('r')) => {
+                return Some(Message::ToggleRegex);
+            }
+            (PromptKind::Search, KeyCode::Char('t')) => {
+                return Some(Message::ToggleSearchIgnoreCase);
+            }
+            _ => {}
+        }
+    }
+
+    match (prompt.kind, key.code) {
+        (_, KeyCode::Esc) => Some(Message::CancelPrompt),
+        (_, KeyCode::Enter) => Some(Message::SubmitPrompt),
+        (PromptKind::Command, KeyCode::Tab) => Some(Message::CompleteCommand),
+        (PromptKind::Command, KeyCode::Up) => Some(Message::RecallCommand(Direction::Backward)),
+        (PromptKind::Command, KeyCode::Down) => Some(Message::RecallCommand(Direction::Forward)),
+        _ => input_action(key).map(Message::EditPrompt),
+    }
+}
+
+// keys shared by every text input
+fn input_action(key: event::KeyEvent) -> Option<InputAction> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        return match key.code {
+            KeyCode::Char('u') => Some(InputAction::Clear),
+            KeyCode::Char('a') => Some(InputAction::Home),
+            KeyCode::Char('e') => Some(InputAction::End),
+            _ => None,
+        };
+    }
+
+    match key.code {
+        KeyCode::Backspace => Some(InputAction::Backspace),
+        KeyCode::Delete => Some(InputAction::Delete),
+        KeyCode::Left => Some(InputAction::Left),
+        KeyCode::Right => Some(InputAction::Right),
+        KeyCode::Home => Some(InputAction::Home),
+        KeyCode::End => Some(InputAction::End),
+        KeyCode::Char(c) => Some(InputAction::Insert(c)),
+        _ => None,
+    }
+}
+
+// any key that isn't part of the menu closes it
+fn handle_menu_key(menu: Menu, key: event::KeyEvent) -> Message {
+    match (menu, key.code) {
+        (Menu::Sort, KeyCode::Char('a')) => Message::Sort(SortKind::Lexical),
+        (Menu::Sort, KeyCode::Char('i')) => Message::Sort(SortKind::IgnoreCase),
+        (Menu::Sort, KeyCode::Char('n')) => Message::Sort(SortKind::Natural),
+        (Menu::Sort, KeyCode::Char('N')) => Message::Sort(SortKind::Numeric),
+        (Menu::Sort, KeyCode::Char('l')) => Message::Sort(SortKind::Length),
+        (Menu::Sort, KeyCode::Char('r')) => Message::Sort(SortKind::Reverse),
+        (Menu::Sort, KeyCode::Char('k')) => Message::OpenPrompt(PromptKind::SortKey),
+        (Menu::Sort, KeyCode::Char('c')) => Message::OpenPrompt(PromptKind::SortCommand),
+        (Menu::Dedupe, KeyCode::Char('d')) => Message::Dedupe(DedupeOptions::default()),
+        (Menu::Dedupe, KeyCode::Char('i')) => Message::Dedupe(DedupeOptions {
+            ignore_case: true,
+            trim: false,
+        }),
+        (Menu::Dedupe, KeyCode::Char('w')) => Message::Dedupe(DedupeOptions {
+            ignore_case: false,
+            trim: true,
+        }),
+        (Menu::Dedupe, KeyCode::Char('b')) => Message::Dedupe(DedupeOptions {
+            ignore_case: true,
+            trim: true,
+        }),
+        (Menu::Register, KeyCode::Char(c)) if is_valid_name(c) => Message::SelectRegister(c),
+        (Menu::Select, KeyCode::Char('a')) => Message::SelectAll,
+        (Menu::Select, KeyCode::Char('i')) => Message::InvertSelection,
+        (Menu::Select, KeyCode::Char('r')) => Message::OpenPrompt(PromptKind::Select),
+        _ => Message::CloseMenu,
+    }
+}
+
 pub(crate) fn update(model: &mut Model, msg: Message) -> Option<Message> {
-    model.message = None;
-    match msg {
+    let shown = model.message.take();
+    // a register applies to the command right after it
+    if !matches!(
+        msg,
+        Message::SelectRegister(_)
+            | Message::AppendToCount(_)
+            | Message::PendingKey(_)
+            | Message::OpenMenu(_)
+            | Message::DeleteLines
+            | Message::YankLines
+            | Message::Paste(_)
+    ) {
+        model.pending_register = None;
+    }
+    // anything but moving around ends visual mode, keeping what it selected
+    if !matches!(
+        msg,
+        Message::AppendToCount(_)
+            | Message::PendingKey(_)
+            | Message::ClearPendingKeys
+            | Message::ScrollHalfPage(_)
+            | Message::GoToNextItem
+            | Message::GoToPreviousPreview
+            | Message::GoToFirstItem
+            | Message::GoToLastItem
+            | Message::GoToMatch(_)
+            | Message::ToggleVisualMode
+            | Message::Quit
+    ) {
+        model.visual = None;
+    }
+
+    // and so does a count
+    let count = match msg {
+        Message::AppendToCount(_) | Message::PendingKey(_) => None,
+        _ => model.count.take(),
+    };
+    // and so do keys that start a longer binding
+    if !matches!(msg, Message::PendingKey(_)) {
+        model.partial_keys.clear();
+    }
+
+    let before = can_change_lines(&msg).then(|| Snapshot::of(&model.lines));
+    let next = match msg {
+        Message::PendingKey(chord) => {
+            model.partial_keys.push(chord);
+            None
+        }
+        Message::ClearPendingKeys => None,
+        Message::AppendToCount(digit) => append_to_count(model, digit),
         Message::MoveToIndex(i) => move_to_index(model, i),
-        Message::GoToNextItem => select_next(model),
-        Message::GoToPreviousPreview => select_previous(model),
+        Message::MoveBelow(n) => move_below(model, n),
+        Message::MoveItemBelow(from, n) => move_item_below(model, from, n),
+        Message::SwapItems(a, b) => swap_items(model, a, b),
+        Message::ReverseRange(start, end) => reverse_range(model, start, end),
+        Message::GoToItem(i) => go_to_item(model, i),
+        Message::GoToNextItem => repeat(model, count, select_next),
+        Message::GoToPreviousPreview => repeat(model, count, select_previous),
         Message::GoToLastItem => select_last(model),
         Message::GoToFirstItem => select_first(model),
-        Message::SwitchWithNextItem => switch_with_next(model),
-        Message::SwitchWithPreviousItem => switch_with_previous(model),
+        Message::ScrollHalfPage(direction) => scroll_half_page(model, direction),
+        Message::SwitchWithNextItem => repeat(model, count, switch_with_next),
+        Message::SwitchWithPreviousItem => repeat(model, count, switch_with_previous),
         Message::MoveToTop => move_to_top(model),
+        Message::MoveToBottom => move_to_bottom(model),
+        Message::MoveSelectionNextToCursor(direction) => {
+            move_selection_next_to_cursor(model, direction)
+        }
         Message::ToggleSelection => toggle_current(model),
+        Message::ToggleVisualMode => toggle_visual_mode(model),
+        Message::SelectAll => select_all(model),
+        Message::InvertSelection => invert_selection(model),
+        Message::UnselectAll => {
+            unselect_selected_items(model);
+            None
+        }
+        Message::SelectMatching(pattern) => {
+            select_matching(model, &pattern);
+            None
+        }
+        Message::Undo => return undo(model),
+        Message::Redo => return redo(model),
         Message::SaveSelection => save_selection(model),
+        Message::WriteTo(path) => write_to(model, &path),
         Message::ShowView(v) => show_view(model, v),
+        Message::OpenPrompt(kind) => open_prompt(model, kind),
+        Message::EditPrompt(action) => edit_prompt(model, action),
+        Message::SubmitPrompt => submit_prompt(model),
+        Message::CancelPrompt => cancel_prompt(model),
+        Message::ToggleRegex => toggle_regex(model),
+        Message::ToggleSearchIgnoreCase => {
+            toggle_search_option(model, |s| s.ignore_case = !s.ignore_case)
+        }
+        Message::GoToMatch(direction) => go_to_match(model, direction),
+        Message::CompleteCommand => complete_command(model),
+        Message::RecallCommand(direction) => recall_command(model, direction),
+        Message::OpenMenu(menu) => open_menu(model, menu),
+        Message::CloseMenu => close_menu(model),
+        Message::Sort(kind) => sort_lines(model, kind),
+        Message::SortByKey(spec) => match KeySpec::parse(&spec) {
+            Ok(spec) => sort_lines_by_key(model, &spec),
+            Err(e) => {
+                model.message = Some(UserMessage::Error(format!("invalid sort key: {e}")));
+                None
+            }
+        },
+        Message::SortByCommand(command) => start_sort_command(model, &command),
+        Message::Shuffle(seed) => shuffle_lines(model, seed.unwrap_or_else(random_seed)),
+        Message::SortKeysComputed(results) => store_sort_keys(model, results),
+        Message::CancelSortKeys => cancel_sort_keys(model),
+        Message::Dedupe(options) => dedupe(model, options),
+        Message::OpenEdit(kind) => open_edit(model, kind),
+        Message::EditLine(action) => edit_line(model, action),
+        Message::SubmitEdit => submit_edit(model),
+        Message::CancelEdit => cancel_edit(model),
+        Message::DeleteLines => delete_lines(model),
+        Message::SelectRegister(name) => select_register(model, name),
+        Message::YankLines => yank_lines(model),
+        Message::Paste(direction) => paste(model, direction),
+        Message::ResolveConflict(r) => resolve_conflict(model, r),
+        Message::FileChangedOnDisk => reload_changed_file(model),
+        // escape drops a count that's been typed in
+        Message::Quit if count.is_some() => None,
         Message::Quit => go_back_or_quit(model),
+        Message::Batch(messages) => run_batch(model, messages),
+        Message::WithCount(count, message) => {
+            model.count = Some(count);
+            Some(*message)
+        }
+    };
+
+    if let Some(before) = before
+        && before.differs_from(&model.lines)
+    {
+        model.history.record(before);
+    }
+    if model.visual.is_some() {
+        select_visual_range(model);
+    }
+    // messages stay up until they expire or get replaced; an old error isn't
+    // reported again on exit
+    match model.message {
+        Some(_) => model.message_expires_at = Some(Instant::now() + MESSAGE_TIMEOUT),
+        None if model.running_state == RunningState::Running => model.message = shown,
+        None => {}
+    }
+
+    next
+}
+
+// moving around, typing and selecting never change the lines, so there's no
+// need to copy them for the history
+fn can_change_lines(msg: &Message) -> bool {
+    !matches!(
+        msg,
+        Message::PendingKey(_)
+            | Message::ClearPendingKeys
+            | Message::AppendToCount(_)
+            | Message::GoToItem(_)
+            | Message::GoToNextItem
+            | Message::GoToPreviousPreview
+            | Message::GoToFirstItem
+            | Message::GoToLastItem
+            | Message::ScrollHalfPage(_)
+            | Message::GoToMatch(_)
+            | Message::ToggleSelection
+            | Message::ToggleVisualMode
+            | Message::SelectAll
+            | Message::InvertSelection
+            | Message::UnselectAll
+            | Message::SelectMatching(_)
+            | Message::Undo
+            | Message::Redo
+            | Message::ShowView(_)
+            | Message::OpenPrompt(_)
+            | Message::EditPrompt(_)
+            | Message::CancelPrompt
+            | Message::ToggleRegex
+            | Message::ToggleSearchIgnoreCase
+            | Message::CompleteCommand
+            | Message::RecallCommand(_)
+            | Message::OpenMenu(_)
+            | Message::CloseMenu
+            | Message::OpenEdit(_)
+            | Message::EditLine(_)
+            | Message::CancelEdit
+            | Message::SelectRegister(_)
+            | Message::YankLines
+            | Message::WithCount(..)
+    )
+}
+
+// the steps share the caller's history entry, so they're undone together; a
+// step that fails stops the ones after it
+fn run_batch(model: &mut Model, messages: Vec<Message>) -> Option<Message> {
+    let history = std::mem::take(&mut model.history);
+    for message in messages {
+        let mut next = Some(message);
+        while let Some(message) = next {
+            next = update(model, message);
+        }
+        if matches!(model.message, Some(UserMessage::Error(_))) {
+            break;
+        }
     }
+    model.history = history;
+    None
 }
 
-fn move_to_index(model: &mut Model, index: usize) -> Option<Message> {
-    if model.selected_count > 0 {
-        model.message = Some(UserMessage::Error("remove selection first".to_string()));
-        return None;
+fn append_to_count(model: &mut Model, digit: usize) -> Option<Message> {
+    let count = model.count.unwrap_or_default();
+    model.count = Some(count.saturating_mul(10).saturating_add(digit));
+    None
+}
+
+// runs a command as many times as the count says; going past the number of
+// lines wouldn't change anything, so that's where it stops
+fn repeat(
+    model: &mut Model,
+    count: Option<usize>,
+    command: fn(&mut Model) -> Option<Message>,
+) -> Option<Message> {
+    let times = count.unwrap_or(1).min(model.lines.items.len().max(1));
+    for _ in 0..times {
+        command(model);
     }
-    if index > model.lines.items.len() - 1 {
+    None
+}
+
+// with a selection, the selected items are moved as a group, so that the
+// first of them ends up at `index`; the group has to fit before the end
+fn move_to_index(model: &mut Model, index: usize) -> Option<Message> {
+    let moved = model.lines.selected_count().max(1);
+    if index + moved > model.lines.items.len() {
         model.message = Some(UserMessage::Error("index is out of range".to_string()));
         return None;
     }
+    if model.lines.selected_count() > 0 {
+        return move_selection_to(model, index);
+    }
 
-    let current = model.lines.state.selected();
+    let current = model.lines.cursor();
     if let Some(i) = current {
         if i == index {
             return None;
         }
         let removed = model.lines.items.remove(i);
         model.lines.items.insert(index, removed);
-        model.lines.state.select(Some(index));
+        model.lines.set_cursor(index);
     }
 
     None
 }
 
-fn move_selection_to_top(model: &mut Model) -> Option<Message> {
-    if model.selected_count == model.lines.items.len() {
-        model.lines.items.iter_mut().for_each(|i| {
-            i.status = false;
-        });
-        model.selected_count = 0;
-        return None;
-    }
-
-    let mut selected: Vec<LineItem> = Vec::with_capacity(model.selected_count);
+// takes the selected items out of the list and puts them back, in the same
+// order, starting at `index` (an index in the list without them); they're
+// unselected afterwards
+fn move_selection_to(model: &mut Model, index: usize) -> Option<Message> {
+    let selected_count = model.lines.selected_count();
+    let mut selected: Vec<LineItem> = Vec::with_capacity(selected_count);
     let mut unselected: Vec<LineItem> =
-        Vec::with_capacity(model.lines.items.len() - model.selected_count);
+        Vec::with_capacity(model.lines.items.len() - selected_count);
 
-    model.lines.items.iter().for_each(|i| match i.status {
-        true => {
-            let mut item = i.clone();
-            item.status = false;
-            selected.push(item);
-        }
-        false => unselected.push(i.clone()),
-    });
+    std::mem::take(&mut model.lines.items)
+        .into_iter()
+        .for_each(|mut item| match item.status {
+            true => {
+                item.status = false;
+                selected.push(item);
+            }
+            false => unselected.push(item),
+        });
 
-    if selected.is_empty() {
-        return None;
+    model.lines.items = unselected;
+    if !selected.is_empty() {
+        insert_lines(model, index, selected);
     }
 
-    selected.extend(unselected);
-    model.lines.items = selected;
-    model.lines.state.select(Some(0));
-    model.selected_count = 0;
-
     None
 }
 
@@ -131,6 +506,17 @@ fn select_previous(model: &mut Model) -> Option<Message> {
     model.lines.state.select_previous();
     None
 }
+// the list clamps the cursor when it's drawn
+fn scroll_half_page(model: &mut Model, direction: Direction) -> Option<Message> {
+    let distance = (model.list_height / 2).max(1);
+    let position = model.lines.state.selected().unwrap_or_default();
+    model.lines.state.select(Some(match direction {
+        Direction::Forward => position.saturating_add(distance),
+        Direction::Backward => position.saturating_sub(distance),
+    }));
+    None
+}
+
 fn select_first(model: &mut Model) -> Option<Message> {
     model.lines.state.select_first();
     None
@@ -139,314 +525,3206 @@ fn select_last(model: &mut Model) -> Option<Message> {
     model.lines.state.select_last();
     None
 }
+// with a filter applied, items switch places with their visible neighbours,
+// while the hidden ones in between stay where they are
 fn switch_with_next(model: &mut Model) -> Option<Message> {
-    let current = model.lines.state.selected();
+    switch_with_neighbour(model, Direction::Forward)
+}
+fn switch_with_previous(model: &mut Model) -> Option<Message> {
+    switch_with_neighbour(model, Direction::Backward)
+}
+fn switch_with_neighbour(model: &mut Model, direction: Direction) -> Option<Message> {
+    if model.lines.selected_count() > 0 {
+        return shift_selection(model, direction);
+    }
+
+    let current = model.lines.cursor();
     if let Some(i) = current {
-        if i == model.lines.items.len() - 1 {
-            return None;
+        let j = model.lines.visible_neighbour(i, direction)?;
+        model.lines.items.swap(i, j);
+        model.lines.set_cursor(j);
+    }
+    None
+}
+
+// every selected item hops over its unselected (visible) neighbour; items
+// that would run past the end of the list stay, and so do the selected items
+// stacked up behind them
+fn shift_selection(model: &mut Model, direction: Direction) -> Option<Message> {
+    let mut visible = model.lines.visible();
+    if direction == Direction::Forward {
+        visible.reverse();
+    }
+    let mut cursor = model.lines.cursor();
+
+    for pair in visible.windows(2) {
+        let (neighbour, i) = (pair[0], pair[1]);
+        let items = &mut model.lines.items;
+        if !items[i].status || items[neighbour].status {
+            continue;
         }
-        model.lines.items.swap(i, i + 1);
-        model.lines.state.select_next();
+        items.swap(i, neighbour);
+        cursor = cursor.map(|c| match c {
+            c if c == i => neighbour,
+            c if c == neighbour => i,
+            c => c,
+        });
+    }
+
+    if let Some(c) = cursor {
+        model.lines.set_cursor(c);
     }
     None
 }
-fn switch_with_previous(model: &mut Model) -> Option<Message> {
-    let current = model.lines.state.selected();
+
+fn move_to_top(model: &mut Model) -> Option<Message> {
+    match model.lines.selected_count() {
+        0 => move_item_to_top(model),
+        _ => move_selection_to(model, 0),
+    }
+}
+
+// like vim's `:move`, `n` counts lines in the current order, including the
+// ones being moved
+fn move_below(model: &mut Model, n: usize) -> Option<Message> {
+    if n > model.lines.items.len() {
+        model.message = Some(UserMessage::Error("index is out of range".to_string()));
+        return None;
+    }
+
+    if model.lines.selected_count() > 0 {
+        let index = model.lines.items[..n]
+            .iter()
+            .filter(|item| !item.status)
+            .count();
+        return move_selection_to(model, index);
+    }
+
+    let current = model.lines.cursor()?;
+    move_item_below(model, current, n)
+}
+
+fn move_item_below(model: &mut Model, from: usize, n: usize) -> Option<Message> {
+    let len = model.lines.items.len();
+    if from >= len || n > len {
+        model.message = Some(UserMessage::Error("index is out of range".to_string()));
+        return None;
+    }
+
+    let index = match from < n {
+        true => n - 1,
+        false => n,
+    };
+    let item = model.lines.items.remove(from);
+    model.lines.items.insert(index, item);
+    model.lines.set_cursor(index);
+    None
+}
+
+// the cursor stays on the line it was on
+fn swap_items(model: &mut Model, a: usize, b: usize) -> Option<Message> {
+    let len = model.lines.items.len();
+    if a >= len || b >= len {
+        model.message = Some(UserMessage::Error("index is out of range".to_string()));
+        return None;
+    }
+
+    let cursor = model.lines.cursor();
+    model.lines.items.swap(a, b);
+    match cursor {
+        Some(c) if c == a => model.lines.set_cursor(b),
+        Some(c) if c == b => model.lines.set_cursor(a),
+        _ => {}
+    }
+    None
+}
+
+fn reverse_range(model: &mut Model, start: usize, end: usize) -> Option<Message> {
+    if start > end || end >= model.lines.items.len() {
+        model.message = Some(UserMessage::Error("index is out of range".to_string()));
+        return None;
+    }
+
+    let cursor = model.lines.cursor();
+    model.lines.items[start..=end].reverse();
+    if let Some(c) = cursor
+        && (start..=end).contains(&c)
+    {
+        model.lines.set_cursor(start + end - c);
+    }
+    None
+}
+
+fn go_to_item(model: &mut Model, index: usize) -> Option<Message> {
+    if index >= model.lines.items.len() {
+        model.message = Some(UserMessage::Error("index is out of range".to_string()));
+        return None;
+    }
+    model.lines.set_cursor(index);
+    None
+}
+
+fn move_to_bottom(model: &mut Model) -> Option<Message> {
+    match model.lines.selected_count() {
+        0 => move_item_to_bottom(model),
+        _ => move_selection_to(model, model.lines.items.len()),
+    }
+}
+
+// keeps the selected items in the order they're in; if the cursor is on one of
+// them, the group is moved to where the cursor is
+fn move_selection_next_to_cursor(model: &mut Model, direction: Direction) -> Option<Message> {
+    if model.lines.selected_count() == 0 {
+        model.message = Some(UserMessage::Error("select lines to move first".to_string()));
+        return None;
+    }
+
+    let cursor = model.lines.cursor()?;
+    let unselected_before = model.lines.items[..cursor]
+        .iter()
+        .filter(|item| !item.status)
+        .count();
+    let index = match (model.lines.items[cursor].status, direction) {
+        (false, Direction::Forward) => unselected_before + 1,
+        _ => unselected_before,
+    };
+
+    move_selection_to(model, index)
+}
+
+fn move_item_to_bottom(model: &mut Model) -> Option<Message> {
+    let current = model.lines.cursor();
     if let Some(i) = current {
-        if i == 0 {
-            return None;
+        let last = model.lines.items.len() - 1;
+        model.lines.items[i..].rotate_left(1);
+        model.lines.set_cursor(last);
+    }
+    None
+}
+
+fn move_item_to_top(model: &mut Model) -> Option<Message> {
+    let current = model.lines.cursor();
+    if let Some(i) = current {
+        match i {
+            0 => (),
+            1 => model.lines.items.swap(0, 1),
+            _ => {
+                model.lines.items[0..i + 1].rotate_right(1);
+            }
+        };
+        model.lines.set_cursor(0);
+    }
+    None
+}
+
+fn toggle_current(model: &mut Model) -> Option<Message> {
+    let current = model.lines.cursor();
+    if let Some(i) = current {
+        model.lines.items[i].toggle();
+    }
+    select_next(model)
+}
+
+fn toggle_visual_mode(model: &mut Model) -> Option<Message> {
+    if model.visual.take().is_some() {
+        return None;
+    }
+
+    let anchor = model.lines.cursor()?;
+    model.visual = Some(Visual {
+        anchor,
+        before: model.lines.items.iter().map(|item| item.status).collect(),
+    });
+    None
+}
+
+// with a filter applied, only the visible lines in the range are selected
+fn select_visual_range(model: &mut Model) {
+    let (Some(visual), Some(cursor)) = (&model.visual, model.lines.cursor()) else {
+        return;
+    };
+    let range = visual.anchor.min(cursor)..=visual.anchor.max(cursor);
+    let visible = model.lines.visible();
+
+    for (i, item) in model.lines.items.iter_mut().enumerate() {
+        item.status = visual.before.get(i).copied().unwrap_or_default()
+            || (range.contains(&i) && visible.binary_search(&i).is_ok());
+    }
+}
+
+// leaves the lines as they were before visual mode started
+fn cancel_visual_mode(model: &mut Model) {
+    let Some(visual) = model.visual.take() else {
+        return;
+    };
+    for (item, status) in model.lines.items.iter_mut().zip(visual.before) {
+        item.status = status;
+    }
+}
+
+fn select_all(model: &mut Model) -> Option<Message> {
+    model.menu = None;
+    for i in model.lines.visible() {
+        model.lines.items[i].status = true;
+    }
+    None
+}
+
+fn invert_selection(model: &mut Model) -> Option<Message> {
+    model.menu = None;
+    for i in model.lines.visible() {
+        model.lines.items[i].toggle();
+    }
+    None
+}
+
+// adds the visible lines that match the pattern to the selection
+fn select_matching(model: &mut Model, pattern: &str) {
+    let search = Search::new(pattern, true, false);
+    if !search.is_valid() {
+        model.message = Some(UserMessage::Error(format!("invalid regex: {pattern}")));
+        return;
+    }
+
+    let mut count = 0;
+    for i in model.lines.visible() {
+        let item = &mut model.lines.items[i];
+        if search.is_match(&item.content) {
+            item.status = true;
+            count += 1;
+        }
+    }
+
+    model.message = Some(match count {
+        0 => UserMessage::Info(format!("no lines match: {pattern}")),
+        1 => UserMessage::Success("selected 1 line".to_string()),
+        n => UserMessage::Success(format!("selected {n} lines")),
+    });
+}
+
+fn complete_command(model: &mut Model) -> Option<Message> {
+    let prompt = model.prompt.as_mut()?;
+    let completed = complete(prompt.input.value())?;
+    prompt.input = TextInput::with_value(&completed);
+    None
+}
+
+// going forward past the latest command leaves an empty prompt
+fn recall_command(model: &mut Model, direction: Direction) -> Option<Message> {
+    let prompt = model.prompt.as_mut()?;
+    let history = &model.command_history;
+    let recalled = match (prompt.recalled, direction) {
+        (None, Direction::Backward) => history.len().checked_sub(1),
+        (Some(i), Direction::Backward) => Some(i.saturating_sub(1)),
+        (Some(i), Direction::Forward) if i + 1 < history.len() => Some(i + 1),
+        (_, Direction::Forward) => None,
+    };
+
+    prompt.recalled = recalled;
+    prompt.input = match recalled {
+        Some(i) => TextInput::with_value(&history[i]),
+        None => TextInput::default(),
+    };
+    None
+}
+
+fn open_prompt(model: &mut Model, kind: PromptKind) -> Option<Message> {
+    match kind {
+        PromptKind::Search => {
+            let previous = model.search.take().unwrap_or_default();
+            model.search = Some(Search::new("", previous.regex, previous.ignore_case));
+        }
+        PromptKind::Filter => {
+            let previous = model.lines.filter.take().unwrap_or_default();
+            model.lines.filter = Some(Filter::new("", previous.regex));
         }
-        model.lines.items.swap(i, i - 1);
-        model.lines.state.select_previous();
+        PromptKind::SortKey | PromptKind::SortCommand | PromptKind::Select => model.menu = None,
+        PromptKind::Command => {}
     }
+
+    model.prompt = Some(Prompt {
+        kind,
+        input: TextInput::default(),
+        origin: model.lines.cursor(),
+        recalled: None,
+    });
     None
 }
 
-fn move_to_top(model: &mut Model) -> Option<Message> {
-    match model.selected_count {
-        0 => move_item_to_top(model),
-        _ => move_selection_to_top(model),
+fn edit_prompt(model: &mut Model, action: InputAction) -> Option<Message> {
+    let prompt = model.prompt.as_mut()?;
+    prompt.input.apply(action);
+
+    match prompt.kind {
+        PromptKind::Search => search_incrementally(model),
+        PromptKind::Filter => filter_incrementally(model),
+        PromptKind::SortKey
+        | PromptKind::SortCommand
+        | PromptKind::Select
+        | PromptKind::Command => {}
+    }
+    None
+}
+
+fn submit_prompt(model: &mut Model) -> Option<Message> {
+    let prompt = model.prompt.take()?;
+
+    match prompt.kind {
+        PromptKind::Search => {
+            let search = model.search.take()?;
+            if search.query.is_empty() {
+                return None;
+            }
+            if !search.is_valid() {
+                model.message = Some(UserMessage::Error(format!(
+                    "invalid regex: {}",
+                    search.query
+                )));
+            } else if find_visible_match(&model.lines, &search, 0, Direction::Forward).is_none() {
+                model.message = Some(UserMessage::Error(format!(
+                    "pattern not found: {}",
+                    search.query
+                )));
+            }
+            model.search = Some(search);
+        }
+        PromptKind::Filter => {
+            let filter = model.lines.filter.take()?;
+            if filter.query.is_empty() {
+                return None;
+            }
+            if !filter.is_valid() {
+                model.message = Some(UserMessage::Error(format!(
+                    "invalid regex: {}",
+                    filter.query
+                )));
+                return None;
+            }
+            model.lines.filter = Some(filter);
+            if model.lines.visible().is_empty() {
+                model.message = Some(UserMessage::Info("no lines match the filter".to_string()));
+            }
+        }
+        PromptKind::SortKey => match KeySpec::parse(prompt.input.value()) {
+            Ok(spec) => return sort_lines_by_key(model, &spec),
+            Err(e) => model.message = Some(UserMessage::Error(format!("invalid sort key: {e}"))),
+        },
+        PromptKind::SortCommand => match prompt.input.value().trim() {
+            "" => {}
+            command => return start_sort_command(model, command),
+        },
+        PromptKind::Select => match prompt.input.value() {
+            "" => {}
+            pattern => select_matching(model, pattern),
+        },
+        PromptKind::Command => match prompt.input.value().trim() {
+            "" => {}
+            command => {
+                if model.command_history.last().map(String::as_str) != Some(command) {
+                    model.command_history.push(command.to_string());
+                }
+                match parse_command(command) {
+                    Ok(message) => return Some(message),
+                    Err(e) => model.message = Some(UserMessage::Error(e)),
+                }
+            }
+        },
+    }
+    None
+}
+
+fn cancel_prompt(model: &mut Model) -> Option<Message> {
+    let prompt = model.prompt.take()?;
+
+    match prompt.kind {
+        PromptKind::Search => model.search = None,
+        PromptKind::Filter => model.lines.filter = None,
+        PromptKind::SortKey
+        | PromptKind::SortCommand
+        | PromptKind::Select
+        | PromptKind::Command => {}
+    }
+    if let Some(origin) = prompt.origin {
+        model.lines.set_cursor(origin);
+    }
+    None
+}
+
+fn toggle_regex(model: &mut Model) -> Option<Message> {
+    match model.prompt.as_ref().map(|p| p.kind) {
+        Some(PromptKind::Search) => toggle_search_option(model, |s| s.regex = !s.regex),
+        Some(PromptKind::Filter) => {
+            if let Some(filter) = model.lines.filter.as_mut() {
+                filter.regex = !filter.regex;
+                filter_incrementally(model);
+            }
+            None
+        }
+        Some(
+            PromptKind::SortKey
+            | PromptKind::SortCommand
+            | PromptKind::Select
+            | PromptKind::Command,
+        )
+        | None => None,
+    }
+}
+
+fn toggle_search_option(model: &mut Model, toggle: fn(&mut Search)) -> Option<Message> {
+    if let Some(search) = model.search.as_mut() {
+        toggle(search);
+        search_incrementally(model);
+    }
+    None
+}
+
+// jumps to the first match at or after the position the search was started
+// from, or back to that position if nothing matches
+fn search_incrementally(model: &mut Model) {
+    let (Some(prompt), Some(previous)) = (&model.prompt, &model.search) else {
+        return;
+    };
+    let search = Search::new(prompt.input.value(), previous.regex, previous.ignore_case);
+    let origin = prompt.origin.unwrap_or(0);
+
+    let from = model.lines.visible().partition_point(|i| *i < origin);
+    let found = find_visible_match(&model.lines, &search, from, Direction::Forward);
+    model.lines.set_cursor(found.unwrap_or(origin));
+    model.search = Some(search);
+}
+
+// keeps the cursor on the line it was on when the filter was started, as long
+// as that line is still visible
+fn filter_incrementally(model: &mut Model) {
+    let (Some(prompt), Some(previous)) = (&model.prompt, &model.lines.filter) else {
+        return;
+    };
+    let filter = Filter::new(prompt.input.value(), previous.regex);
+    let origin = prompt.origin.unwrap_or(0);
+
+    model.lines.filter = Some(filter);
+    model.lines.set_cursor(origin);
+}
+
+fn go_to_match(model: &mut Model, direction: Direction) -> Option<Message> {
+    let Some(search) = &model.search else {
+        model.message = Some(UserMessage::Error("no search pattern".to_string()));
+        return None;
+    };
+
+    let visible_count = model.lines.visible().len().max(1);
+    let current = model
+        .lines
+        .state
+        .selected()
+        .unwrap_or(0)
+        .min(visible_count - 1);
+    let from = match direction {
+        Direction::Forward => (current + 1) % visible_count,
+        Direction::Backward => (current + visible_count - 1) % visible_count,
+    };
+
+    match find_visible_match(&model.lines, search, from, direction) {
+        Some(i) => model.lines.set_cursor(i),
+        None => {
+            model.message = Some(UserMessage::Error(format!(
+                "pattern not found: {}",
+                search.query
+            )))
+        }
+    }
+    None
+}
+
+// `from` is a position among the visible lines; the returned index is in
+// `items`
+fn find_visible_match(
+    lines: &Lines,
+    search: &Search,
+    from: usize,
+    direction: Direction,
+) -> Option<usize> {
+    let visible = lines.visible();
+    let contents: Vec<&str> = visible
+        .iter()
+        .map(|i| lines.items[*i].content.as_ref())
+        .collect();
+
+    search
+        .find(&contents, from, direction)
+        .map(|position| visible[position])
+}
+
+fn open_menu(model: &mut Model, menu: Menu) -> Option<Message> {
+    model.menu = Some(menu);
+    None
+}
+
+fn close_menu(model: &mut Model) -> Option<Message> {
+    model.menu = None;
+    None
+}
+
+fn sort_lines(model: &mut Model, kind: SortKind) -> Option<Message> {
+    model.menu = None;
+    let count = rearrange_lines(model, |items, indices| sort_in_place(items, indices, kind))?;
+
+    model.message = Some(UserMessage::Success(format!(
+        "sorted {count} lines {}",
+        kind.label()
+    )));
+    None
+}
+
+fn sort_lines_by_key(model: &mut Model, spec: &KeySpec) -> Option<Message> {
+    let count = rearrange_lines(model, |items, indices| {
+        sort_by_key_spec(items, indices, spec)
+    })?;
+
+    model.message = Some(UserMessage::Success(format!("sorted {count} lines by key")));
+    None
+}
+
+// keys already in the cache aren't computed again; everything else is handed to
+// a background job, and the lines are sorted once it's done
+fn start_sort_command(model: &mut Model, command: &str) -> Option<Message> {
+    let cached = model.key_cache.entry(command.to_string()).or_default();
+    let mut seen = HashSet::new();
+    let missing: Vec<(Vec<u8>, String)> = reorder_targets(&model.lines)
+        .into_iter()
+        .map(|i| &model.lines.items[i])
+        .filter(|item| !cached.contains_key(item.raw.as_ref()) && seen.insert(item.raw.as_ref()))
+        .map(|item| (item.raw.to_vec(), item.content.to_string()))
+        .collect();
+
+    if missing.is_empty() {
+        return sort_lines_by_command(model, command, 0);
+    }
+
+    model.key_job = Some(KeyJob::start(command, missing));
+    None
+}
+
+// keys for lines the command failed on aren't cached, so that they're tried
+// again next time
+fn store_sort_keys(model: &mut Model, results: Vec<KeyResult>) -> Option<Message> {
+    let job = model.key_job.as_mut()?;
+    let cache = model.key_cache.entry(job.command.clone()).or_default();
+
+    job.done += results.len();
+    for (raw, key) in results {
+        match key {
+            Ok(key) => {
+                cache.insert(raw, key);
+            }
+            Err(_) => job.failed += 1,
+        }
+    }
+
+    if !job.is_finished() {
+        return None;
+    }
+    let job = model.key_job.take()?;
+    sort_lines_by_command(model, &job.command, job.failed)
+}
+
+fn cancel_sort_keys(model: &mut Model) -> Option<Message> {
+    if model.key_job.take().is_some() {
+        model.message = Some(UserMessage::Error("sorting cancelled".to_string()));
+    }
+    None
+}
+
+fn sort_lines_by_command(model: &mut Model, command: &str, failed: usize) -> Option<Message> {
+    let keys = model.key_cache.remove(command).unwrap_or_default();
+    let count = rearrange_lines(model, |items, indices| {
+        sort_by_computed_keys(items, indices, &keys)
+    });
+    model.key_cache.insert(command.to_string(), keys);
+    let count = count?;
+
+    model.message = Some(match failed {
+        0 => UserMessage::Success(format!("sorted {count} lines by command output")),
+        _ => UserMessage::Error(format!(
+            "sorted {count} lines; the command failed for {failed} of them (sorted first)"
+        )),
+    });
+    None
+}
+
+fn open_edit(model: &mut Model, kind: EditKind) -> Option<Message> {
+    let cursor = model.lines.cursor();
+    let (target, value) = match (kind, cursor) {
+        (EditKind::Current, Some(i)) => (
+            EditTarget::Replace(i),
+            model.lines.items[i].content.as_ref(),
+        ),
+        (EditKind::Current, None) => return None,
+        (EditKind::NewAbove, _) => (EditTarget::Insert(cursor.unwrap_or(0)), ""),
+        (EditKind::NewBelow, _) => (EditTarget::Insert(cursor.map_or(0, |i| i + 1)), ""),
+    };
+
+    model.edit = Some(LineEdit {
+        input: TextInput::with_value(value),
+        target,
+    });
+    None
+}
+
+fn edit_line(model: &mut Model, action: InputAction) -> Option<Message> {
+    model.edit.as_mut()?.input.apply(action);
+    None
+}
+
+// lines only change once the edit is submitted, so that every edit is a
+// single step in the history
+fn submit_edit(model: &mut Model) -> Option<Message> {
+    let edit = model.edit.take()?;
+    let encoding = model.lines.encoding;
+    let text = edit.input.value();
+
+    let Some(raw) = encoding.encode(text) else {
+        model.message = Some(UserMessage::Error(
+            "line has characters the file's encoding can't represent".to_string(),
+        ));
+        model.edit = Some(edit);
+        return None;
+    };
+
+    match edit.target {
+        // lines that weren't changed keep their original bytes, even if they
+        // couldn't be decoded
+        EditTarget::Replace(i) if *model.lines.items[i].content == *text => {}
+        EditTarget::Replace(i) => {
+            let item = &model.lines.items[i];
+            let line = RawLine {
+                bytes: raw,
+                ending: item.ending,
+            };
+            model.lines.items[i] = LineItem::new(line, encoding, item.status);
+        }
+        EditTarget::Insert(i) => {
+            model
+                .lines
+                .items
+                .insert(i, LineItem::new(raw.into(), encoding, false));
+            model.lines.set_cursor(i);
+        }
+    }
+    None
+}
+
+fn cancel_edit(model: &mut Model) -> Option<Message> {
+    model.edit = None;
+    None
+}
+
+// the selected lines if there are any, or the current one otherwise
+fn lines_to_cut_or_yank(model: &Model) -> Option<Vec<usize>> {
+    let cursor = model.lines.cursor()?;
+    Some(match model.lines.selected_count() {
+        0 => vec![cursor],
+        _ => (0..model.lines.items.len())
+            .filter(|i| model.lines.items[*i].status)
+            .collect(),
+    })
+}
+
+fn delete_lines(model: &mut Model) -> Option<Message> {
+    model.menu = None;
+    let register = model.pending_register.take();
+    let cursor = model.lines.cursor()?;
+    let indices = lines_to_cut_or_yank(model)?;
+    let deleted_before_cursor = indices.iter().filter(|i| **i < cursor).count();
+
+    let mut deleted: Vec<RawLine> = indices
+        .iter()
+        .rev()
+        .map(|i| model.lines.items.remove(*i).to_raw_line())
+        .collect();
+    deleted.reverse();
+
+    model.message = Some(UserMessage::Success(match deleted.len() {
+        1 => "deleted 1 line".to_string(),
+        n => format!("deleted {n} lines"),
+    }));
+    model.registers.store(register, deleted);
+    model.lines.set_cursor(cursor - deleted_before_cursor);
+    None
+}
+
+fn select_register(model: &mut Model, name: char) -> Option<Message> {
+    model.menu = None;
+    model.pending_register = Some(name);
+    None
+}
+
+fn yank_lines(model: &mut Model) -> Option<Message> {
+    model.menu = None;
+    let register = model.pending_register.take();
+    let indices = lines_to_cut_or_yank(model)?;
+
+    let yanked: Vec<RawLine> = indices
+        .iter()
+        .map(|i| model.lines.items[*i].to_raw_line())
+        .collect();
+    model.message = Some(UserMessage::Success(match yanked.len() {
+        1 => "yanked 1 line".to_string(),
+        n => format!("yanked {n} lines"),
+    }));
+    model.registers.store(register, yanked);
+    None
+}
+
+fn paste(model: &mut Model, direction: Direction) -> Option<Message> {
+    let register = model.pending_register.take();
+    let lines = model.registers.get(register);
+    if lines.is_empty() {
+        model.message = Some(UserMessage::Error(match register {
+            Some(name) => format!("register {name} is empty"),
+            None => "nothing to paste".to_string(),
+        }));
+        return None;
+    }
+
+    let encoding = model.lines.encoding;
+    let items: Vec<LineItem> = lines
+        .iter()
+        .map(|line| LineItem::new(line.clone(), encoding, false))
+        .collect();
+    let index = match (model.lines.cursor(), direction) {
+        (Some(i), Direction::Forward) => i + 1,
+        (Some(i), Direction::Backward) => i,
+        (None, _) => 0,
+    };
+    insert_lines(model, index, items);
+    None
+}
+
+// puts `items` into the list starting at `index`, and moves the cursor to the
+// first of them
+fn insert_lines(model: &mut Model, index: usize, items: Vec<LineItem>) {
+    let index = index.min(model.lines.items.len());
+    model.lines.items.splice(index..index, items);
+    model.lines.set_cursor(index);
+}
+
+// works on the full list, even when it's filtered
+fn dedupe(model: &mut Model, options: DedupeOptions) -> Option<Message> {
+    model.menu = None;
+    let removed = model.lines.dedupe(options);
+
+    model.message = Some(match removed {
+        0 => UserMessage::Info("no duplicate lines".to_string()),
+        1 => UserMessage::Success("removed 1 duplicate line".to_string()),
+        n => UserMessage::Success(format!("removed {n} duplicate lines")),
+    });
+    None
+}
+
+fn shuffle_lines(model: &mut Model, seed: u64) -> Option<Message> {
+    let count = rearrange_lines(model, |items, indices| {
+        shuffle_in_place(items, indices, seed)
+    })?;
+
+    model.last_seed = Some(seed);
+    model.message = Some(UserMessage::Success(format!(
+        "shuffled {count} lines (seed: {seed})"
+    )));
+    None
+}
+
+// reorders the selected lines among the positions they're at, or the visible
+// ones if nothing is selected; the cursor stays on the line it was on.
+// `reorder` returns the index each line came from. Returns how many lines were
+// reordered, if there were enough to do anything with.
+fn rearrange_lines(
+    model: &mut Model,
+    reorder: impl FnOnce(&mut [LineItem], &[usize]) -> Vec<usize>,
+) -> Option<usize> {
+    let indices = reorder_targets(&model.lines);
+    if indices.len() < 2 {
+        return None;
+    }
+
+    let cursor = model.lines.cursor();
+    let order = reorder(&mut model.lines.items, &indices);
+    if let Some(cursor) = cursor
+        && let Some(position) = order.iter().position(|i| *i == cursor)
+    {
+        model.lines.set_cursor(indices[position]);
+    }
+
+    Some(indices.len())
+}
+
+fn reorder_targets(lines: &Lines) -> Vec<usize> {
+    match lines.items.iter().any(|item| item.status) {
+        true => (0..lines.items.len())
+            .filter(|i| lines.items[*i].status)
+            .collect(),
+        false => lines.visible(),
+    }
+}
+
+fn undo(model: &mut Model) -> Option<Message> {
+    match model.history.undo(Snapshot::of(&model.lines)) {
+        Some(snapshot) => restore_snapshot(model, snapshot),
+        None => model.message = Some(UserMessage::Info("nothing to undo".to_string())),
+    }
+    None
+}
+
+fn redo(model: &mut Model) -> Option<Message> {
+    match model.history.redo(Snapshot::of(&model.lines)) {
+        Some(snapshot) => restore_snapshot(model, snapshot),
+        None => model.message = Some(UserMessage::Info("nothing to redo".to_string())),
+    }
+    None
+}
+
+fn restore_snapshot(model: &mut Model, snapshot: Snapshot) {
+    snapshot.restore(&mut model.lines);
+}
+
+fn save_selection(model: &mut Model) -> Option<Message> {
+    match &model.target {
+        Target::File(file_path) => {
+            let file_path = file_path.clone();
+            if changed_on_disk(model, &file_path) {
+                model.conflict = Some(Conflict { quit_after: false });
+            } else {
+                write_lines_to_file(model, file_path);
+            }
+        }
+        Target::Stdout => confirm_output(model),
+    }
+    None
+}
+
+// writing to the file being shuffled is the same as saving
+fn write_to(model: &mut Model, path: &str) -> Option<Message> {
+    if let Target::File(file_path) = &model.target
+        && let (Ok(a), Ok(b)) = (fs::canonicalize(file_path), fs::canonicalize(path))
+        && a == b
+    {
+        return save_selection(model);
+    }
+
+    let items: Vec<LineRef> = model
+        .lines
+        .items
+        .iter()
+        .map(LineItem::as_line_ref)
+        .collect();
+
+    model.message = Some(
+        match write_to_path(&items, path, &model.format, model.backup) {
+            Ok(_) => UserMessage::Success(format!("written to {path}")),
+            Err(e) => UserMessage::Error(format!("couldn't write to {path}; error: {e}")),
+        },
+    );
+    None
+}
+
+// if the file can't be read anymore, writing to it will surface the error
+fn changed_on_disk(model: &Model, file_path: &str) -> bool {
+    match (&model.fingerprint, fingerprint_file(file_path)) {
+        (Some(known), Ok(current)) => *known != current,
+        _ => false,
+    }
+}
+
+fn write_lines_to_file(model: &mut Model, file_path: String) {
+    let items: Vec<LineRef> = model
+        .lines
+        .items
+        .iter()
+        .map(LineItem::as_line_ref)
+        .collect();
+
+    let write_result = write_to_file(&items, file_path.as_str(), &model.format, model.backup);
+    match write_result {
+        Ok(_) => {
+            model.fingerprint = fingerprint_file(&file_path).ok();
+            model.saved = model.lines.items.iter().map(|i| i.raw.clone()).collect();
+            model.message = Some(UserMessage::Success("written to file".to_string()));
+        }
+        Err(e) => {
+            model.message = Some(UserMessage::Error(format!(
+                "couldn't write to file; error: {e}"
+            )))
+        }
+    }
+}
+
+// in pipe mode, saving hands the lines (only the selected ones, if there's a
+// selection) over to main, which prints them once the terminal is restored
+fn confirm_output(model: &mut Model) {
+    let only_selected = model.lines.selected_count() > 0;
+    let output: Vec<LineRef> = model
+        .lines
+        .items
+        .iter()
+        .filter(|item| !only_selected || item.status)
+        .map(LineItem::as_line_ref)
+        .collect();
+
+    model.output = Some(model.format.join(&output));
+    model.running_state = RunningState::Done;
+}
+
+fn resolve_conflict(model: &mut Model, resolution: ConflictResolution) -> Option<Message> {
+    let conflict = model.conflict.take()?;
+    let Target::File(file_path) = &model.target else {
+        return None;
+    };
+    let file_path = file_path.clone();
+
+    match resolution {
+        ConflictResolution::Overwrite => write_lines_to_file(model, file_path),
+        ConflictResolution::Reload => {
+            if let Some(lines) = reread_file(model, &file_path) {
+                model.lines = Lines::new(lines, model.lines.encoding);
+                model.saved = model.lines.items.iter().map(|i| i.raw.clone()).collect();
+                model.message = Some(UserMessage::Success("reloaded file".to_string()));
+            }
+            return None;
+        }
+        ConflictResolution::Merge => {
+            let lines = reread_file(model, &file_path)?;
+            model.lines.merge(&model.saved, &lines, model.new_lines_at);
+            model.saved = lines.into_iter().map(|line| line.bytes.into()).collect();
+            write_lines_to_file(model, file_path);
+        }
+        ConflictResolution::Cancel => return None,
+    }
+
+    if conflict.quit_after {
+        model.running_state = RunningState::Done;
+    }
+
+    None
+}
+
+// keeps the current order and the changes made here; the fingerprint check
+// filters out changes shfl made itself. Edits point at lines by index, so
+// reloading waits until they're done.
+fn reload_changed_file(model: &mut Model) -> Option<Message> {
+    if model.edit.is_some() || model.prompt.is_some() {
+        model.reload_pending = true;
+        return None;
+    }
+    model.reload_pending = false;
+
+    let Target::File(file_path) = &model.target else {
+        return None;
+    };
+    let file_path = file_path.clone();
+
+    if !changed_on_disk(model, &file_path) {
+        return None;
+    }
+
+    let lines = reread_file(model, &file_path)?;
+    model.lines.merge(&model.saved, &lines, model.new_lines_at);
+    model.saved = lines.into_iter().map(|line| line.bytes.into()).collect();
+    model.message = Some(UserMessage::Success(
+        "file changed on disk, reloaded".to_string(),
+    ));
+
+    None
+}
+
+fn reread_file(model: &mut Model, file_path: &str) -> Option<Vec<RawLine>> {
+    match read_lines_with_fingerprint(file_path) {
+        Ok((lines, format, fingerprint)) => {
+            model.fingerprint = Some(fingerprint);
+            model.format = format;
+            Some(lines)
+        }
+        Err(e) => {
+            model.message = Some(UserMessage::Error(format!(
+                "couldn't read file; error: {e}"
+            )));
+            None
+        }
+    }
+}
+
+fn show_view(model: &mut Model, view: View) -> Option<Message> {
+    model.view = match model.view {
+        View::Help => View::List,
+        _ => view,
+    };
+    None
+}
+
+fn unselect_selected_items(model: &mut Model) {
+    model.lines.items.iter_mut().for_each(|i| {
+        i.status = false;
+    });
+}
+
+fn go_back_or_quit(model: &mut Model) -> Option<Message> {
+    if model.visual.is_some() {
+        cancel_visual_mode(model);
+        return None;
+    }
+
+    match model.view {
+        View::List => match model.lines.selected_count() {
+            0 if model.search.is_some() => model.search = None,
+            0 if model.lines.filter.is_some() => {
+                let cursor = model.lines.cursor();
+                model.lines.filter = None;
+                if let Some(i) = cursor {
+                    model.lines.set_cursor(i);
+                }
+            }
+            0 => {
+                if model.save_on_exit {
+                    let _ = save_selection(model);
+                    if let Some(conflict) = model.conflict.as_mut() {
+                        conflict.quit_after = true;
+                        return None;
+                    }
+                }
+                model.running_state = RunningState::Done;
+            }
+            _ => unselect_selected_items(model),
+        },
+        View::Help => model.view = View::List,
+    };
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Keymap;
+    use crate::message::EditKind;
+    use crate::model::NewLinesPosition;
+    use crate::utils::Encoding;
+
+    #[test]
+    fn move_item_to_top_works() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+
+        model.lines.state.select(Some(2));
+
+        // WHEN
+        let message = move_item_to_top(&mut model);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+
+        assert_eq!(content, vec!["2", "0", "1", "3", "4"]);
+    }
+
+    #[test]
+    fn move_to_index_works() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+
+        model.lines.state.select(Some(4));
+
+        // WHEN
+        let message = move_to_index(&mut model, 1);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+
+        assert_eq!(content, vec!["0", "4", "1", "2", "3"]);
+    }
+
+    #[test]
+    fn move_to_index_works_handles_an_index_out_of_bounds() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+
+        model.lines.state.select(Some(4));
+
+        // WHEN
+        let message = move_to_index(&mut model, 6);
+
+        // THEN
+        assert!(message.is_none());
+        assert!(model.message.is_some());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+
+        assert_eq!(content, vec!["0", "1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn a_typed_position_moves_the_item_past_the_ninth_line() {
+        // GIVEN
+        let lines: Vec<String> = (0..30).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        update(&mut model, Message::AppendToCount(2));
+        update(&mut model, Message::AppendToCount(3));
+
+        // WHEN
+        let message = handle_key(&model, event::KeyEvent::from(KeyCode::Enter));
+
+        // THEN
+        assert!(message == Some(Message::MoveToIndex(22)));
+        update(&mut model, message.unwrap());
+        assert_eq!(&*model.lines.items[22].content, "0");
+        assert_eq!(model.lines.cursor(), Some(22));
+        assert_eq!(model.count, None);
+    }
+
+    #[test]
+    fn a_count_repeats_moves() {
+        // GIVEN
+        let lines: Vec<String> = (0..10).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+
+        // WHEN
+        update(&mut model, Message::AppendToCount(5));
+        update(&mut model, Message::SwitchWithNextItem);
+        update(&mut model, Message::SwitchWithNextItem);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(
+            content,
+            vec!["0", "2", "3", "4", "5", "6", "7", "1", "8", "9"]
+        );
+        assert_eq!(model.lines.cursor(), Some(7));
+    }
+
+    #[test]
+    fn escape_drops_a_count_without_quitting() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        update(&mut model, Message::AppendToCount(4));
+
+        // WHEN
+        update(&mut model, Message::Quit);
+
+        // THEN
+        assert_eq!(model.count, None);
+        assert_eq!(model.running_state, RunningState::Running);
+        assert!(handle_key(&model, event::KeyEvent::from(KeyCode::Char('0'))).is_none());
+    }
+
+    #[test]
+    fn key_sequences_wait_for_their_last_key() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let d = event::KeyEvent::from(KeyCode::Char('d'));
+
+        // WHEN
+        let first = handle_key(&model, d);
+        assert!(matches!(first, Some(Message::PendingKey(_))));
+        update(&mut model, first.unwrap());
+        let second = handle_key(&model, d);
+
+        // THEN
+        assert_eq!(second, Some(Message::DeleteLines));
+        update(&mut model, second.unwrap());
+        assert!(model.partial_keys.is_empty());
+        assert_eq!(model.lines.items.len(), 2);
+    }
+
+    #[test]
+    fn an_unbound_key_drops_a_pending_sequence() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        update(
+            &mut model,
+            Message::PendingKey(KeyChord::parse("y").unwrap()),
+        );
+
+        // WHEN
+        let message = handle_key(&model, event::KeyEvent::from(KeyCode::Char('x')));
+
+        // THEN
+        assert_eq!(message, Some(Message::ClearPendingKeys));
+        update(&mut model, message.unwrap());
+        assert!(model.partial_keys.is_empty());
+    }
+
+    #[test]
+    fn remapped_keys_send_their_new_action() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let bindings = [
+            ("ctrl+s".to_string(), "write".to_string()),
+            ("q".to_string(), "none".to_string()),
+            ("X".to_string(), "sort -n".to_string()),
+        ];
+        model.keymap = Keymap::with_bindings(&bindings.into_iter().collect()).unwrap();
+
+        // WHEN
+        let save = handle_key(
+            &model,
+            event::KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL),
+        );
+        let quit = handle_key(&model, event::KeyEvent::from(KeyCode::Char('q')));
+        let sort = handle_key(&model, event::KeyEvent::from(KeyCode::Char('X')));
+
+        // THEN
+        assert_eq!(save, Some(Message::SaveSelection));
+        assert_eq!(quit, None);
+        assert!(matches!(sort, Some(Message::Sort(_))));
+    }
+
+    #[test]
+    fn scrolling_half_a_page_stays_within_the_list() {
+        // GIVEN
+        let lines: Vec<String> = (0..30).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.list_height = 20;
+        model.lines.state.select(Some(4));
+
+        // WHEN
+        update(&mut model, Message::ScrollHalfPage(Direction::Forward));
+        let after_down = model.lines.cursor();
+        update(&mut model, Message::ScrollHalfPage(Direction::Backward));
+        update(&mut model, Message::ScrollHalfPage(Direction::Backward));
+
+        // THEN
+        assert_eq!(after_down, Some(14));
+        assert_eq!(model.lines.cursor(), Some(0));
+    }
+
+    #[test]
+    fn move_selection_to_top_works() {
+        // GIVEN
+        let lines: Vec<String> = (0..10).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[1..3]
+            .iter_mut()
+            .for_each(|i| i.status = true);
+        model.lines.items[5].status = true;
+        model.lines.items[7..9]
+            .iter_mut()
+            .for_each(|i| i.status = true);
+
+        // WHEN
+        let message = move_selection_to(&mut model, 0);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+
+        assert_eq!(
+            content,
+            vec!["1", "2", "5", "7", "8", "0", "3", "4", "6", "9"]
+        );
+    }
+
+    #[test]
+    fn move_to_bottom_moves_the_selection_as_a_group() {
+        // GIVEN
+        let lines: Vec<String> = (0..6).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[1].status = true;
+        model.lines.items[3].status = true;
+
+        // WHEN
+        let message = update(&mut model, Message::MoveToBottom);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "2", "4", "5", "1", "3"]);
+        assert_eq!(model.lines.cursor(), Some(4));
+        assert_eq!(model.lines.selected_count(), 0);
+    }
+
+    #[test]
+    fn move_to_bottom_moves_the_current_item_without_a_selection() {
+        // GIVEN
+        let lines: Vec<String> = (0..4).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+
+        // WHEN
+        update(&mut model, Message::MoveToBottom);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "2", "3", "1"]);
+        assert_eq!(model.lines.cursor(), Some(3));
+    }
+
+    #[test]
+    fn selection_can_be_moved_next_to_the_cursor() {
+        // GIVEN
+        let lines: Vec<String> = (0..6).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[0].status = true;
+        model.lines.items[5].status = true;
+        model.lines.state.select(Some(2));
+
+        // WHEN
+        update(
+            &mut model,
+            Message::MoveSelectionNextToCursor(Direction::Forward),
+        );
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["1", "2", "0", "5", "3", "4"]);
+        assert_eq!(model.lines.cursor(), Some(2));
+    }
+
+    #[test]
+    fn selection_can_be_moved_above_the_cursor() {
+        // GIVEN
+        let lines: Vec<String> = (0..6).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[0].status = true;
+        model.lines.items[5].status = true;
+        model.lines.state.select(Some(2));
+
+        // WHEN
+        update(
+            &mut model,
+            Message::MoveSelectionNextToCursor(Direction::Backward),
+        );
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["1", "0", "5", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn moving_next_to_the_cursor_needs_a_selection() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+
+        // WHEN
+        update(
+            &mut model,
+            Message::MoveSelectionNextToCursor(Direction::Forward),
+        );
+
+        // THEN
+        assert!(matches!(model.message, Some(UserMessage::Error(_))));
+    }
+
+    #[test]
+    fn move_to_index_moves_the_selection_as_a_group() {
+        // GIVEN
+        let lines: Vec<String> = (0..6).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[0].status = true;
+        model.lines.items[1].status = true;
+
+        // WHEN
+        update(&mut model, Message::MoveToIndex(2));
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["2", "3", "0", "1", "4", "5"]);
+        assert_eq!(model.lines.cursor(), Some(2));
+    }
+
+    #[test]
+    fn move_to_index_rejects_an_index_the_selection_doesnt_fit_at() {
+        // GIVEN
+        let lines: Vec<String> = (0..10).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[0].status = true;
+        model.lines.items[1].status = true;
+
+        // WHEN
+        update(&mut model, Message::MoveToIndex(9));
+        let rejected = matches!(model.message, Some(UserMessage::Error(_)));
+        update(&mut model, Message::MoveToIndex(8));
+
+        // THEN
+        assert!(rejected);
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(
+            content,
+            vec!["2", "3", "4", "5", "6", "7", "8", "9", "0", "1"]
+        );
+    }
+
+    #[test]
+    fn switch_with_next_moves_the_selection_as_a_block() {
+        // GIVEN
+        let lines: Vec<String> = (0..7).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        [1, 2, 4]
+            .iter()
+            .for_each(|&i| model.lines.items[i].status = true);
+        model.lines.state.select(Some(2));
+
+        // WHEN
+        let message = update(&mut model, Message::SwitchWithNextItem);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "3", "1", "2", "5", "4", "6"]);
+        assert_eq!(model.lines.cursor(), Some(3));
+        assert_eq!(model.lines.selected_count(), 3);
+    }
+
+    #[test]
+    fn selection_stops_at_the_end_of_the_list() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        [1, 3, 4]
+            .iter()
+            .for_each(|&i| model.lines.items[i].status = true);
+
+        // WHEN
+        update(&mut model, Message::SwitchWithNextItem);
+        update(&mut model, Message::SwitchWithNextItem);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "2", "1", "3", "4"]);
+    }
+
+    #[test]
+    fn switch_with_previous_moves_the_selection_as_a_block() {
+        // GIVEN
+        let lines: Vec<String> = (0..6).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        [0, 2, 3, 5]
+            .iter()
+            .for_each(|&i| model.lines.items[i].status = true);
+        model.lines.state.select(Some(5));
+
+        // WHEN
+        update(&mut model, Message::SwitchWithPreviousItem);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "2", "3", "1", "5", "4"]);
+        assert_eq!(model.lines.cursor(), Some(4));
+    }
+
+    #[test]
+    fn switch_with_next_works() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(2));
+
+        // WHEN
+        let message = switch_with_next(&mut model);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+
+        assert_eq!(content, vec!["0", "1", "3", "2", "4"]);
+    }
+
+    #[test]
+    fn switch_with_previous_works() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(2));
+
+        // WHEN
+        let message = switch_with_previous(&mut model);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+
+        assert_eq!(content, vec!["0", "2", "1", "3", "4"]);
+    }
+
+    #[test]
+    fn visual_mode_selects_the_lines_between_the_anchor_and_the_cursor() {
+        // GIVEN
+        let lines: Vec<String> = (0..8).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[7].status = true;
+        model.lines.state.select(Some(3));
+
+        // WHEN
+        update(&mut model, Message::ToggleVisualMode);
+        update(&mut model, Message::GoToNextItem);
+        update(&mut model, Message::GoToNextItem);
+        update(&mut model, Message::GoToPreviousPreview);
+        update(&mut model, Message::GoToPreviousPreview);
+        update(&mut model, Message::GoToPreviousPreview);
+
+        // THEN
+        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+        assert_eq!(
+            statuses,
+            vec![false, false, true, true, false, false, false, true]
+        );
+        assert!(model.visual.is_some());
+    }
+
+    #[test]
+    fn other_commands_end_visual_mode_keeping_the_selection() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(2));
+        update(&mut model, Message::ToggleVisualMode);
+        update(&mut model, Message::GoToNextItem);
+
+        // WHEN
+        update(&mut model, Message::MoveToTop);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["2", "3", "0", "1", "4"]);
+        assert!(model.visual.is_none());
+    }
+
+    #[test]
+    fn escape_cancels_visual_mode() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[0].status = true;
+        model.lines.state.select(Some(2));
+        update(&mut model, Message::ToggleVisualMode);
+        update(&mut model, Message::GoToLastItem);
+
+        // WHEN
+        update(&mut model, Message::Quit);
+
+        // THEN
+        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+        assert_eq!(statuses, vec![true, false, false, false, false]);
+        assert!(model.visual.is_none());
+        assert_eq!(model.running_state, RunningState::Running);
+    }
+
+    #[test]
+    fn select_all_and_invert_selection_only_touch_visible_lines() {
+        // GIVEN
+        let lines: Vec<String> = ["a1", "b1", "a2", "b2"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[1].status = true;
+        model.lines.filter = Some(Filter::new("a", false));
+
+        // WHEN
+        update(&mut model, Message::InvertSelection);
+        let inverted: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+        update(&mut model, Message::SelectAll);
+
+        // THEN
+        assert_eq!(inverted, vec![true, true, true, false]);
+        assert_eq!(model.lines.selected_count(), 3);
+    }
+
+    #[test]
+    fn lines_can_be_selected_by_regex() {
+        // GIVEN
+        let lines: Vec<String> = ["main", "feat/one", "fix/two", "feat/three"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+
+        // WHEN
+        update(&mut model, Message::OpenPrompt(PromptKind::Select));
+        type_into_prompt(&mut model, "^fe.*/");
+        update(&mut model, Message::SubmitPrompt);
+
+        // THEN
+        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+        assert_eq!(statuses, vec![false, true, false, true]);
+        assert!(matches!(model.message, Some(UserMessage::Success(_))));
+    }
+
+    #[test]
+    fn selecting_by_an_invalid_regex_shows_an_error() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+
+        // WHEN
+        update(&mut model, Message::OpenPrompt(PromptKind::Select));
+        type_into_prompt(&mut model, "(");
+        update(&mut model, Message::SubmitPrompt);
+
+        // THEN
+        assert!(matches!(model.message, Some(UserMessage::Error(_))));
+        assert_eq!(model.lines.selected_count(), 0);
+    }
+
+    #[test]
+    fn toggle_current_works() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+
+        // WHEN
+        let message_one = toggle_current(&mut model);
+        let message_two = toggle_current(&mut model);
+        let message_three = toggle_current(&mut model);
+
+        // THEN
+        assert!(message_one.is_none());
+        assert!(message_two.is_none());
+        assert!(message_three.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+
+        assert_eq!(content, vec!["0", "1", "2", "3", "4"]);
+        assert_eq!(statuses, vec![false, true, true, true, false]);
+        assert_eq!(model.lines.selected_count(), 3);
+        assert_eq!(model.lines.state.selected(), Some(4));
+    }
+
+    #[test]
+    fn selection_can_be_reset() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+
+        // WHEN
+        let _ = toggle_current(&mut model);
+        let _ = toggle_current(&mut model);
+        let _ = toggle_current(&mut model);
+        let message = go_back_or_quit(&mut model);
+
+        // THEN
+        assert!(message.is_none());
+        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+
+        assert_eq!(statuses, vec![false, false, false, false, false]);
+        assert_eq!(model.lines.selected_count(), 0);
+        assert_eq!(model.lines.state.selected(), Some(4));
+    }
+
+    #[test]
+    fn saving_in_pipe_mode_outputs_selected_lines_and_quits() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(Target::Stdout, Lines::from(&lines), false);
+        model.lines.state.select(Some(3));
+        let _ = update(&mut model, Message::ToggleSelection);
+        let _ = update(&mut model, Message::MoveToTop);
+        model.lines.state.select(Some(1));
+        let _ = update(&mut model, Message::ToggleSelection);
+        let _ = update(&mut model, Message::ToggleSelection);
+
+        // WHEN
+        let message = update(&mut model, Message::SaveSelection);
+
+        // THEN
+        assert!(message.is_none());
+        assert_eq!(model.running_state, RunningState::Done);
+        assert_eq!(model.output, Some(b"0\n1\n".to_vec()));
+    }
+
+    #[test]
+    fn quitting_in_pipe_mode_outputs_nothing() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(Target::Stdout, Lines::from(&lines), false);
+
+        // WHEN
+        let _ = update(&mut model, Message::Quit);
+
+        // THEN
+        assert_eq!(model.running_state, RunningState::Done);
+        assert!(model.output.is_none());
+    }
+
+    // the directory is removed once the returned guard is dropped
+    fn model_for_file(name: &str, content: &str) -> (Model, std::path::PathBuf, tempfile::TempDir) {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("shfl-{name}-"))
+            .tempdir()
+            .expect("temp dir should've been created");
+        let path = dir.path().join("lines.txt");
+        std::fs::write(&path, content).expect("file should've been written");
+
+        let (lines, format, fingerprint) =
+            read_lines_with_fingerprint(path.to_str().unwrap()).unwrap();
+        let mut model = Model::default(
+            Target::File(path.to_str().unwrap().to_string()),
+            Lines::new(lines, Encoding::Utf8),
+            false,
+        );
+        model.fingerprint = Some(fingerprint);
+        model.format = format;
+
+        (model, path, dir)
+    }
+
+    #[test]
+    fn the_file_is_modified_until_the_new_order_is_saved() {
+        // GIVEN
+        let (mut model, _, _dir) = model_for_file("modified", "0\n1\n2\n");
+        let unchanged = model.is_modified();
+
+        // WHEN
+        update(&mut model, Message::SwitchWithNextItem);
+        let moved = model.is_modified();
+        update(&mut model, Message::SaveSelection);
+
+        // THEN
+        assert!(!unchanged);
+        assert!(moved);
+        assert!(!model.is_modified());
+    }
+
+    #[test]
+    fn messages_stay_up_until_they_expire() {
+        // GIVEN
+        let (mut model, _, _dir) = model_for_file("expiry", "0\n1\n");
+        update(&mut model, Message::SaveSelection);
+
+        // WHEN
+        update(&mut model, Message::GoToNextItem);
+        let after_key = model.message.as_ref().map(|m| m.value());
+        model.message_expires_at = Some(Instant::now());
+        let _ = handle_event(&mut model);
+
+        // THEN
+        assert_eq!(after_key, Some("written to file".to_string()));
+        assert!(model.message.is_none());
+    }
+
+    #[test]
+    fn saving_a_file_changed_on_disk_asks_before_overwriting() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("conflict", "0\n1\n2\n");
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::MoveToTop);
+        std::fs::write(&path, "0\n1\n2\n3\n").unwrap();
+
+        // WHEN
+        let message = update(&mut model, Message::SaveSelection);
+
+        // THEN
+        assert!(message.is_none());
+        assert_eq!(model.conflict, Some(Conflict { quit_after: false }));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "0\n1\n2\n3\n");
+    }
+
+    #[test]
+    fn merging_with_a_file_changed_on_disk_keeps_current_order() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("merge", "0\n1\n2\n");
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::MoveToTop);
+        std::fs::write(&path, "0\n2\n3\n").unwrap();
+        let _ = update(&mut model, Message::SaveSelection);
+
+        // WHEN
+        let message = update(
+            &mut model,
+            Message::ResolveConflict(ConflictResolution::Merge),
+        );
+
+        // THEN
+        assert!(message.is_none());
+        assert!(model.conflict.is_none());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "2\n0\n3\n");
+    }
+
+    #[test]
+    fn merging_keeps_lines_edited_inserted_and_deleted_here() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("merge-edits", "a\nb\n");
+        let _ = update(&mut model, Message::OpenEdit(EditKind::NewAbove));
+        type_into_line(&mut model, "mine");
+        let _ = update(&mut model, Message::SubmitEdit);
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::DeleteLines);
+        std::fs::write(&path, "a\nb\nc\n").unwrap();
+        let _ = update(&mut model, Message::SaveSelection);
+
+        // WHEN
+        let _ = update(
+            &mut model,
+            Message::ResolveConflict(ConflictResolution::Merge),
+        );
+
+        // THEN
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "mine\na\nc\n");
+    }
+
+    #[test]
+    fn save_on_exit_waits_for_conflict_to_be_resolved() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("exit-conflict", "0\n1\n");
+        model.save_on_exit = true;
+        std::fs::write(&path, "1\n0\n").unwrap();
+
+        // WHEN
+        let _ = update(&mut model, Message::Quit);
+
+        // THEN
+        assert_eq!(model.running_state, RunningState::Running);
+        assert_eq!(model.conflict, Some(Conflict { quit_after: true }));
+
+        // WHEN
+        let _ = update(
+            &mut model,
+            Message::ResolveConflict(ConflictResolution::Overwrite),
+        );
+
+        // THEN
+        assert_eq!(model.running_state, RunningState::Done);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "0\n1\n");
+    }
+
+    #[test]
+    fn changes_on_disk_are_reloaded_keeping_current_order() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("reload", "0\n1\n2\n3\n");
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::MoveToTop);
+        std::fs::write(&path, "0\n4\n2\n3\n5\n").unwrap();
+
+        // WHEN
+        let message = update(&mut model, Message::FileChangedOnDisk);
+
+        // THEN
+        assert!(message.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["2", "0", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn new_lines_can_be_placed_at_the_top_on_reload() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("reload-top", "0\n1\n2\n");
+        model.new_lines_at = NewLinesPosition::Top;
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::SwitchWithPreviousItem);
+        std::fs::write(&path, "0\n1\n2\n3\n4\n").unwrap();
+
+        // WHEN
+        let _ = update(&mut model, Message::FileChangedOnDisk);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["3", "4", "0", "2", "1"]);
+        assert_eq!(model.lines.cursor(), Some(3));
+    }
+
+    #[test]
+    fn the_cursor_moves_to_the_next_line_if_its_line_is_removed_on_disk() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("reload-cursor", "0\n1\n2\n");
+        model.new_lines_at = NewLinesPosition::Top;
+        model.lines.state.select(Some(1));
+        std::fs::write(&path, "0\n2\n3\n").unwrap();
+
+        // WHEN
+        let _ = update(&mut model, Message::FileChangedOnDisk);
+
+        // THEN
+        let cursor = model
+            .lines
+            .cursor()
+            .map(|i| model.lines.items[i].content.as_ref());
+        assert_eq!(cursor, Some("2"));
+    }
+
+    #[test]
+    fn changes_on_disk_are_reloaded_once_the_edit_is_done() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("reload-edit", "0\n1\n2\n");
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::OpenEdit(EditKind::Current));
+        type_into_line(&mut model, "!");
+        std::fs::write(&path, "0\n").unwrap();
+
+        // WHEN
+        let _ = update(&mut model, Message::FileChangedOnDisk);
+        let _ = update(&mut model, Message::SubmitEdit);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "1", "2!"]);
+
+        // WHEN
+        let message = handle_event(&mut model).unwrap();
+        let _ = update(&mut model, Message::FileChangedOnDisk);
+
+        // THEN
+        assert_eq!(message, Some(Message::FileChangedOnDisk));
+        assert!(!model.reload_pending);
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "2!"]);
+    }
+
+    #[test]
+    fn saving_keeps_line_endings_and_missing_trailing_newline() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("crlf", "0\r\n1\r\n2");
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::MoveToTop);
+
+        // WHEN
+        let _ = update(&mut model, Message::SaveSelection);
+
+        // THEN
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "2\r\n0\r\n1");
+    }
+
+    fn type_into_prompt(model: &mut Model, text: &str) {
+        text.chars().for_each(|c| {
+            let _ = update(model, Message::EditPrompt(InputAction::Insert(c)));
+        });
+    }
+
+    #[test]
+    fn search_jumps_to_matches_while_typing() {
+        // GIVEN
+        let lines: Vec<String> = ["alpha", "beta", "gamma", "beta-2", "delta"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::Search));
+
+        // WHEN
+        type_into_prompt(&mut model, "bet");
+
+        // THEN
+        assert_eq!(model.lines.state.selected(), Some(3));
+
+        // WHEN
+        type_into_prompt(&mut model, "x");
+
+        // THEN
+        assert_eq!(model.lines.state.selected(), Some(2));
+    }
+
+    #[test]
+    fn cancelling_search_restores_cursor() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::Search));
+        type_into_prompt(&mut model, "4");
+
+        // WHEN
+        let _ = update(&mut model, Message::CancelPrompt);
+
+        // THEN
+        assert!(model.prompt.is_none());
+        assert!(model.search.is_none());
+        assert_eq!(model.lines.state.selected(), Some(1));
+    }
+
+    #[test]
+    fn going_to_matches_wraps_around() {
+        // GIVEN
+        let lines: Vec<String> = ["Feat: a", "fix: b", "feat: c", "chore: d"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::Search));
+        let _ = update(&mut model, Message::ToggleSearchIgnoreCase);
+        type_into_prompt(&mut model, "feat");
+        let _ = update(&mut model, Message::SubmitPrompt);
+
+        // WHEN
+        let _ = update(&mut model, Message::GoToMatch(Direction::Forward));
+        let after_next = model.lines.state.selected();
+        let _ = update(&mut model, Message::GoToMatch(Direction::Forward));
+        let after_wrapping = model.lines.state.selected();
+        let _ = update(&mut model, Message::GoToMatch(Direction::Backward));
+        let after_previous = model.lines.state.selected();
+
+        // THEN
+        assert_eq!(after_next, Some(2));
+        assert_eq!(after_wrapping, Some(0));
+        assert_eq!(after_previous, Some(2));
+    }
+
+    #[test]
+    fn moves_in_a_filtered_list_apply_to_the_full_list() {
+        // GIVEN
+        let lines: Vec<String> = ["work/api", "personal", "work/web", "misc", "work/db"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::Filter));
+        type_into_prompt(&mut model, "work");
+        let _ = update(&mut model, Message::SubmitPrompt);
+        let _ = update(&mut model, Message::GoToLastItem);
+
+        // WHEN
+        let _ = update(&mut model, Message::SwitchWithPreviousItem);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(
+            content,
+            vec!["work/api", "personal", "work/db", "misc", "work/web"]
+        );
+        assert_eq!(model.lines.visible(), vec![0, 2, 4]);
+        assert_eq!(model.lines.cursor(), Some(2));
+
+        // WHEN
+        let _ = update(&mut model, Message::MoveToTop);
+        let _ = update(&mut model, Message::Quit);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(
+            content,
+            vec!["work/db", "work/api", "personal", "misc", "work/web"]
+        );
+        assert!(model.lines.filter.is_none());
+        assert_eq!(model.running_state, RunningState::Running);
+        assert_eq!(model.lines.cursor(), Some(0));
+    }
+
+    #[test]
+    fn cancelling_filter_shows_all_lines_again() {
+        // GIVEN
+        let lines: Vec<String> = ["alpha", "beta", "gamma"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::Filter));
+        type_into_prompt(&mut model, "gm");
+
+        // WHEN
+        let visible_while_filtering = model.lines.visible();
+        let _ = update(&mut model, Message::CancelPrompt);
+
+        // THEN
+        assert_eq!(visible_while_filtering, vec![2]);
+        assert_eq!(model.lines.visible(), vec![0, 1, 2]);
+        assert_eq!(model.lines.cursor(), Some(1));
+    }
+
+    #[test]
+    fn undo_and_redo_restore_order_and_cursor() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(3));
+        let _ = update(&mut model, Message::MoveToTop);
+        let _ = update(&mut model, Message::SwitchWithNextItem);
+
+        // WHEN
+        let _ = update(&mut model, Message::Undo);
+        let _ = update(&mut model, Message::Undo);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["0", "1", "2", "3", "4"]);
+        assert_eq!(model.lines.state.selected(), Some(3));
+
+        // WHEN
+        let _ = update(&mut model, Message::Redo);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["3", "0", "1", "2", "4"]);
+        assert_eq!(model.lines.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn undo_restores_selection_after_moving_it() {
+        // GIVEN
+        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::ToggleSelection);
+        let _ = update(&mut model, Message::ToggleSelection);
+        let _ = update(&mut model, Message::MoveToTop);
+
+        // WHEN
+        let _ = update(&mut model, Message::Undo);
+
+        // THEN
+        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+        assert_eq!(statuses, vec![false, false, true, true, false]);
+        assert_eq!(model.lines.selected_count(), 2);
+        assert_eq!(model.lines.state.selected(), Some(4));
+    }
+
+    #[test]
+    fn a_new_change_clears_redo_history() {
+        // GIVEN
+        let lines: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+        let _ = update(&mut model, Message::SwitchWithNextItem);
+        let _ = update(&mut model, Message::Undo);
+
+        // WHEN
+        let _ = update(&mut model, Message::SwitchWithPreviousItem);
+        let _ = update(&mut model, Message::Redo);
+
+        // THEN
+        assert!(model.message.is_some());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["1", "0", "2"]);
+    }
+
+    #[test]
+    fn sorting_the_whole_list_can_be_undone() {
+        // GIVEN
+        let lines: Vec<String> = ["v1.10", "v1.9", "v2", "v1.2"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(1));
+        let _ = update(&mut model, Message::OpenMenu(Menu::Sort));
+
+        // WHEN
+        let _ = update(&mut model, Message::Sort(SortKind::Natural));
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["v1.2", "v1.9", "v1.10", "v2"]);
+        assert_eq!(model.lines.cursor(), Some(1));
+        assert!(model.menu.is_none());
+
+        // WHEN
+        let _ = update(&mut model, Message::Undo);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["v1.10", "v1.9", "v2", "v1.2"]);
+    }
+
+    #[test]
+    fn sorting_with_a_selection_leaves_unselected_lines_in_place() {
+        // GIVEN
+        let lines: Vec<String> = ["10", "b", "9", "a", "100"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        for i in [0, 2, 4] {
+            model.lines.state.select(Some(i));
+            let _ = update(&mut model, Message::ToggleSelection);
+        }
+        model.lines.state.select(Some(4));
+
+        // WHEN
+        let _ = update(&mut model, Message::Sort(SortKind::Numeric));
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["9", "b", "10", "a", "100"]);
+        assert_eq!(model.lines.selected_count(), 3);
+        assert_eq!(model.lines.cursor(), Some(4));
+
+        // WHEN
+        let _ = update(&mut model, Message::Sort(SortKind::Reverse));
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["100", "b", "10", "a", "9"]);
+    }
+
+    #[test]
+    fn shuffling_with_a_selection_leaves_unselected_lines_in_place() {
+        // GIVEN
+        let lines: Vec<String> = (0..8).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        for i in [1, 3, 5, 7] {
+            model.lines.state.select(Some(i));
+            let _ = update(&mut model, Message::ToggleSelection);
+        }
+
+        // WHEN
+        let _ = update(&mut model, Message::Shuffle(Some(42)));
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        let unselected: Vec<&str> = content.iter().step_by(2).copied().collect();
+        let mut selected: Vec<&str> = content.iter().skip(1).step_by(2).copied().collect();
+        assert_eq!(unselected, vec!["0", "2", "4", "6"]);
+        selected.sort();
+        assert_eq!(selected, vec!["1", "3", "5", "7"]);
+        assert_eq!(
+            model.message.map(|m| m.value()),
+            Some("shuffled 4 lines (seed: 42)".to_string())
+        );
+        assert_eq!(model.last_seed, Some(42));
+    }
+
+    #[test]
+    fn sorting_by_a_key_from_the_prompt_works() {
+        // GIVEN
+        let lines: Vec<String> = ["b\t10", "a\t9", "c\t100"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenMenu(Menu::Sort));
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::SortKey));
+        type_into_prompt(&mut model, "-t \\t 2nr");
+
+        // WHEN
+        let _ = update(&mut model, Message::SubmitPrompt);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["c\t100", "b\t10", "a\t9"]);
+        assert!(model.menu.is_none());
+        assert!(model.prompt.is_none());
+    }
+
+    #[test]
+    fn an_invalid_sort_key_leaves_lines_untouched() {
+        // GIVEN
+        let lines: Vec<String> = ["b", "a"].iter().map(|l| l.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::SortKey));
+        type_into_prompt(&mut model, "1q");
+
+        // WHEN
+        let _ = update(&mut model, Message::SubmitPrompt);
+
+        // THEN
+        assert_eq!(&*model.lines.items[0].content, "b");
+        assert!(matches!(model.message, Some(UserMessage::Error(_))));
+    }
+
+    #[test]
+    fn sorting_by_command_output_uses_computed_keys_and_caches_them() {
+        // GIVEN
+        let lines: Vec<String> = ["ccc", "a", "bb"].iter().map(|l| l.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::SortCommand));
+        type_into_prompt(&mut model, "printf %s {} | wc -c");
+
+        // WHEN
+        let _ = update(&mut model, Message::SubmitPrompt);
+        let started = std::time::Instant::now();
+        while let Some(job) = model.key_job.as_ref()
+            && started.elapsed() < Duration::from_secs(10)
+        {
+            let results = job.poll();
+            let _ = update(&mut model, Message::SortKeysComputed(results));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["a", "bb", "ccc"]);
+
+        // WHEN
+        let _ = update(&mut model, Message::Sort(SortKind::Reverse));
+        let _ = update(&mut model, Message::OpenPrompt(PromptKind::SortCommand));
+        type_into_prompt(&mut model, "printf %s {} | wc -c");
+        let _ = update(&mut model, Message::SubmitPrompt);
+
+        // THEN
+        assert!(model.key_job.is_none());
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["a", "bb", "ccc"]);
+    }
+
+    #[test]
+    fn dedupe_keeps_the_first_occurrence_in_the_current_order() {
+        // GIVEN
+        let lines: Vec<String> = ["b", "a", "B ", "a", "c", "b"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.state.select(Some(4));
+
+        // WHEN
+        let _ = update(&mut model, Message::Dedupe(DedupeOptions::default()));
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["b", "a", "B ", "c"]);
+        assert_eq!(model.lines.cursor(), Some(3));
+
+        // WHEN
+        let _ = update(
+            &mut model,
+            Message::Dedupe(DedupeOptions {
+                ignore_case: true,
+                trim: true,
+            }),
+        );
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["b", "a", "c"]);
+        assert_eq!(model.lines.cursor(), Some(2));
+
+        // WHEN
+        let _ = update(&mut model, Message::Undo);
+        let _ = update(&mut model, Message::Undo);
+
+        // THEN
+        assert_eq!(model.lines.items.len(), 6);
     }
-}
 
-fn move_item_to_top(model: &mut Model) -> Option<Message> {
-    let current = model.lines.state.selected();
-    if let Some(i) = current {
-        match i {
-            0 => (),
-            1 => model.lines.items.swap(0, 1),
-            _ => {
-                model.lines.items[0..i + 1].rotate_right(1);
-            }
-        };
-        model.lines.state.select_first();
+    #[test]
+    fn dedupe_tells_apart_lines_that_cant_be_decoded() {
+        // GIVEN
+        let lines = [b"\xff".as_slice(), b"\xfe", b" \xfe ", b"A\xff", b"a\xff"]
+            .map(|line| line.to_vec().into())
+            .to_vec();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::new(lines, Encoding::Utf8),
+            false,
+        );
+
+        // WHEN
+        let _ = update(
+            &mut model,
+            Message::Dedupe(DedupeOptions {
+                ignore_case: true,
+                trim: true,
+            }),
+        );
+
+        // THEN
+        let raw: Vec<&[u8]> = model.lines.items.iter().map(|i| i.raw.as_ref()).collect();
+        assert_eq!(raw, vec![b"\xff".as_slice(), b"\xfe", b"A\xff"]);
     }
-    None
-}
 
-fn toggle_current(model: &mut Model) -> Option<Message> {
-    let current = model.lines.state.selected();
-    if let Some(i) = current {
-        let selected = model.lines.items[i].toggle();
-        if selected {
-            model.selected_count += 1;
-        } else {
-            model.selected_count -= 1;
-        };
+    fn type_into_line(model: &mut Model, text: &str) {
+        text.chars().for_each(|c| {
+            let _ = update(model, Message::EditLine(InputAction::Insert(c)));
+        });
     }
-    select_next(model)
-}
 
-fn save_selection(model: &mut Model) -> Option<Message> {
-    let items: Vec<&str> = model
-        .lines
-        .items
-        .iter()
-        .map(|item| item.content.as_str())
-        .collect();
+    #[test]
+    fn inserting_and_editing_lines_are_single_undo_steps() {
+        // GIVEN
+        let lines: Vec<String> = ["alpha", "gamma"].iter().map(|l| l.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenEdit(EditKind::NewBelow));
+        type_into_line(&mut model, "beta");
+        let _ = update(&mut model, Message::SubmitEdit);
+        let _ = update(&mut model, Message::OpenEdit(EditKind::Current));
+        let _ = update(&mut model, Message::EditLine(InputAction::Backspace));
+        type_into_line(&mut model, "!");
 
-    let write_result = write_to_file(items, model.file_path.as_str());
-    match write_result {
-        Ok(_) => model.message = Some(UserMessage::Success("written to file".to_string())),
-        Err(e) => {
-            model.message = Some(UserMessage::Error(format!(
-                "couldn't write to file; error: {e}"
-            )))
-        }
+        // WHEN
+        let _ = update(&mut model, Message::SubmitEdit);
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
+        assert_eq!(content, vec!["alpha", "bet!", "gamma"]);
+        assert_eq!(model.lines.cursor(), Some(1));
+
+        // WHEN
+        let _ = update(&mut model, Message::Undo);
+        let after_one_undo: Vec<String> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.to_string())
+            .collect();
+        let _ = update(&mut model, Message::Undo);
+
+        // THEN
+        assert_eq!(after_one_undo, vec!["alpha", "beta", "gamma"]);
+        assert_eq!(model.lines.items.len(), 2);
     }
-    None
-}
 
-fn show_view(model: &mut Model, view: View) -> Option<Message> {
-    model.view = match model.view {
-        View::Help => View::List,
-        _ => view,
-    };
-    None
-}
+    #[test]
+    fn cancelling_an_edit_leaves_lines_untouched() {
+        // GIVEN
+        let lines: Vec<String> = ["alpha"].iter().map(|l| l.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenEdit(EditKind::NewAbove));
+        type_into_line(&mut model, "beta");
 
-fn unselect_selected_items(model: &mut Model) {
-    model.lines.items.iter_mut().for_each(|i| {
-        i.status = false;
-    });
-    model.selected_count = 0;
-}
+        // WHEN
+        let _ = update(&mut model, Message::CancelEdit);
 
-fn go_back_or_quit(model: &mut Model) -> Option<Message> {
-    match model.view {
-        View::List => match model.selected_count {
-            0 => {
-                if model.save_on_exit {
-                    let _ = save_selection(model);
-                }
-                model.running_state = RunningState::Done;
-            }
-            _ => unselect_selected_items(model),
-        },
-        View::Help => model.view = View::List,
-    };
+        // THEN
+        assert!(model.edit.is_none());
+        assert_eq!(model.lines.items.len(), 1);
+        assert!(model.history.undo(Snapshot::of(&model.lines)).is_none());
+    }
 
-    None
-}
+    #[test]
+    fn edits_are_saved_in_the_files_encoding() {
+        // GIVEN
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::new(vec![b"caf\xe9".to_vec().into()], Encoding::Latin1),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenEdit(EditKind::Current));
+        type_into_line(&mut model, "ñ");
+        let _ = update(&mut model, Message::SubmitEdit);
+        let _ = update(&mut model, Message::OpenEdit(EditKind::NewBelow));
+        type_into_line(&mut model, "✓");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // WHEN
+        let _ = update(&mut model, Message::SubmitEdit);
+
+        // THEN
+        assert_eq!(&*model.lines.items[0].raw, b"caf\xe9\xf1");
+        assert!(model.edit.is_some());
+        assert!(matches!(model.message, Some(UserMessage::Error(_))));
+    }
 
     #[test]
-    fn move_item_to_top_works() {
+    fn deleting_lines_keeps_them_in_the_register() {
         // GIVEN
         let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
         model.lines.state.select(Some(2));
+        let _ = update(&mut model, Message::DeleteLines);
+        for i in [0, 2] {
+            model.lines.state.select(Some(i));
+            let _ = update(&mut model, Message::ToggleSelection);
+        }
 
         // WHEN
-        let message = move_item_to_top(&mut model);
+        let _ = update(&mut model, Message::DeleteLines);
 
         // THEN
-        assert!(message.is_none());
         let content: Vec<&str> = model
             .lines
             .items
             .iter()
-            .map(|i| i.content.as_str())
+            .map(|i| i.content.as_ref())
             .collect();
-
-        assert_eq!(content, vec!["2", "0", "1", "3", "4"]);
+        assert_eq!(content, vec!["1", "4"]);
+        assert_eq!(
+            model.registers.get(None),
+            &[RawLine::from(b"0".to_vec()), RawLine::from(b"3".to_vec())]
+        );
+        assert_eq!(model.lines.selected_count(), 0);
+        assert_eq!(model.lines.cursor(), Some(1));
     }
 
     #[test]
-    fn move_to_index_works() {
+    fn cut_lines_can_be_pasted_anywhere() {
         // GIVEN
         let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-
-        model.lines.state.select(Some(4));
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        for i in [0, 1] {
+            model.lines.state.select(Some(i));
+            let _ = update(&mut model, Message::ToggleSelection);
+        }
+        let _ = update(&mut model, Message::DeleteLines);
+        let _ = update(&mut model, Message::GoToLastItem);
 
         // WHEN
-        let message = move_to_index(&mut model, 1);
+        let _ = update(&mut model, Message::Paste(Direction::Backward));
 
         // THEN
-        assert!(message.is_none());
         let content: Vec<&str> = model
             .lines
             .items
             .iter()
-            .map(|i| i.content.as_str())
+            .map(|i| i.content.as_ref())
             .collect();
+        assert_eq!(content, vec!["2", "3", "0", "1", "4"]);
+        assert_eq!(model.lines.cursor(), Some(2));
 
-        assert_eq!(content, vec!["0", "4", "1", "2", "3"]);
+        // WHEN
+        let _ = update(&mut model, Message::Undo);
+
+        // THEN
+        assert_eq!(model.lines.items.len(), 3);
     }
 
     #[test]
-    fn move_to_index_works_handles_an_index_out_of_bounds() {
+    fn named_registers_are_kept_apart_from_the_unnamed_one() {
         // GIVEN
-        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-
-        model.lines.state.select(Some(4));
+        let lines: Vec<String> = ["a", "b", "c"].iter().map(|l| l.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::OpenMenu(Menu::Register));
+        let _ = update(&mut model, Message::SelectRegister('x'));
+        let _ = update(
+            &mut model,
+            Message::PendingKey(KeyChord::parse("y").unwrap()),
+        );
+        let _ = update(&mut model, Message::YankLines);
+        let _ = update(&mut model, Message::GoToNextItem);
+        let _ = update(&mut model, Message::YankLines);
+        let _ = update(&mut model, Message::GoToLastItem);
 
         // WHEN
-        let message = move_to_index(&mut model, 6);
+        let _ = update(&mut model, Message::SelectRegister('x'));
+        let _ = update(&mut model, Message::Paste(Direction::Forward));
+        let _ = update(&mut model, Message::Paste(Direction::Forward));
 
         // THEN
-        assert!(message.is_none());
-        assert!(model.message.is_some());
         let content: Vec<&str> = model
             .lines
             .items
             .iter()
-            .map(|i| i.content.as_str())
+            .map(|i| i.content.as_ref())
             .collect();
+        assert_eq!(content, vec!["a", "b", "c", "a", "b"]);
+        assert!(model.pending_register.is_none());
+    }
 
-        assert_eq!(content, vec!["0", "1", "2", "3", "4"]);
+    #[test]
+    fn a_register_only_applies_to_the_next_command() {
+        // GIVEN
+        let lines: Vec<String> = ["a", "b"].iter().map(|l| l.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        let _ = update(&mut model, Message::SelectRegister('q'));
+
+        // WHEN
+        let _ = update(&mut model, Message::GoToNextItem);
+        let _ = update(&mut model, Message::YankLines);
+
+        // THEN
+        assert!(model.registers.get(Some('q')).is_empty());
+        assert_eq!(model.registers.get(None), &[RawLine::from(b"b".to_vec())]);
+    }
+
+    fn run_command(model: &mut Model, command: &str) {
+        update(model, Message::OpenPrompt(PromptKind::Command));
+        type_into_prompt(model, command);
+        let mut next = update(model, Message::SubmitPrompt);
+        while let Some(message) = next {
+            next = update(model, message);
+        }
     }
 
     #[test]
-    fn move_selection_to_top_works() {
+    fn move_command_works_like_vims() {
         // GIVEN
-        let lines: Vec<String> = (0..10).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-        model.lines.items[1..3]
-            .iter_mut()
-            .for_each(|i| i.status = true);
-        model.lines.items[5].status = true;
-        model.lines.items[7..9]
-            .iter_mut()
-            .for_each(|i| i.status = true);
+        let lines: Vec<String> = (1..=6).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
 
         // WHEN
-        let message = move_selection_to_top(&mut model);
+        run_command(&mut model, "move 2 5");
+        let moved_down: Vec<String> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.to_string())
+            .collect();
+        run_command(&mut model, "move 6 0");
 
         // THEN
-        assert!(message.is_none());
+        assert_eq!(moved_down, vec!["1", "3", "4", "5", "2", "6"]);
         let content: Vec<&str> = model
             .lines
             .items
             .iter()
-            .map(|i| i.content.as_str())
+            .map(|i| i.content.as_ref())
             .collect();
+        assert_eq!(content, vec!["6", "1", "3", "4", "5", "2"]);
+        assert_eq!(model.lines.cursor(), Some(0));
+    }
+
+    #[test]
+    fn move_command_with_two_lines_ignores_the_selection() {
+        // GIVEN
+        let lines: Vec<String> = (1..=10).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        model.lines.items[1].status = true;
+        model.lines.items[2].status = true;
 
+        // WHEN
+        run_command(&mut model, "move 8 9");
+
+        // THEN
+        let content: Vec<&str> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.as_ref())
+            .collect();
         assert_eq!(
             content,
-            vec!["1", "2", "5", "7", "8", "0", "3", "4", "6", "9"]
+            vec!["1", "2", "3", "4", "5", "6", "7", "9", "8", "10"]
         );
+        assert_eq!(model.lines.selected_count(), 2);
+        assert_eq!(model.lines.cursor(), Some(8));
     }
 
     #[test]
-    fn switch_with_next_works() {
+    fn commands_with_several_steps_are_undone_in_one_go() {
         // GIVEN
-        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-        model.lines.state.select(Some(2));
+        let lines: Vec<String> = (1..=5).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        run_command(&mut model, "down 3");
+        let moved: Vec<String> = model
+            .lines
+            .items
+            .iter()
+            .map(|i| i.content.to_string())
+            .collect();
 
         // WHEN
-        let message = switch_with_next(&mut model);
+        update(&mut model, Message::Undo);
 
         // THEN
-        assert!(message.is_none());
+        assert_eq!(moved, vec!["2", "3", "4", "1", "5"]);
         let content: Vec<&str> = model
             .lines
             .items
             .iter()
-            .map(|i| i.content.as_str())
+            .map(|i| i.content.as_ref())
             .collect();
-
-        assert_eq!(content, vec!["0", "1", "3", "2", "4"]);
+        assert_eq!(content, vec!["1", "2", "3", "4", "5"]);
     }
 
     #[test]
-    fn switch_with_previous_works() {
+    fn swap_and_reverse_commands_work() {
         // GIVEN
-        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-        model.lines.state.select(Some(2));
+        let lines: Vec<String> = (1..=6).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
 
         // WHEN
-        let message = switch_with_previous(&mut model);
+        run_command(&mut model, "swap 1 2");
+        run_command(&mut model, "reverse 3,6");
 
         // THEN
-        assert!(message.is_none());
         let content: Vec<&str> = model
             .lines
             .items
             .iter()
-            .map(|i| i.content.as_str())
+            .map(|i| i.content.as_ref())
             .collect();
-
-        assert_eq!(content, vec!["0", "2", "1", "3", "4"]);
+        assert_eq!(content, vec!["2", "1", "6", "5", "4", "3"]);
+        assert_eq!(model.lines.cursor(), Some(1));
     }
 
     #[test]
-    fn toggle_current_works() {
+    fn invalid_commands_show_an_error() {
         // GIVEN
-        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-        model.lines.state.select(Some(1));
+        let lines: Vec<String> = (1..=3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
 
         // WHEN
-        let message_one = toggle_current(&mut model);
-        let message_two = toggle_current(&mut model);
-        let message_three = toggle_current(&mut model);
+        run_command(&mut model, "move 1 9");
 
         // THEN
-        assert!(message_one.is_none());
-        assert!(message_two.is_none());
-        assert!(message_three.is_none());
+        assert!(matches!(model.message, Some(UserMessage::Error(_))));
         let content: Vec<&str> = model
             .lines
             .items
             .iter()
-            .map(|i| i.content.as_str())
+            .map(|i| i.content.as_ref())
             .collect();
-        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+        assert_eq!(content, vec!["1", "2", "3"]);
+    }
 
-        assert_eq!(content, vec!["0", "1", "2", "3", "4"]);
-        assert_eq!(statuses, vec![false, true, true, true, false]);
-        assert_eq!(model.selected_count, 3);
-        assert_eq!(model.lines.state.selected(), Some(4));
+    #[test]
+    fn previous_commands_can_be_recalled() {
+        // GIVEN
+        let lines: Vec<String> = (1..=3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        run_command(&mut model, "top");
+        run_command(&mut model, "sort -n");
+        run_command(&mut model, "sort -n");
+
+        // WHEN
+        update(&mut model, Message::OpenPrompt(PromptKind::Command));
+        update(&mut model, Message::RecallCommand(Direction::Backward));
+        update(&mut model, Message::RecallCommand(Direction::Backward));
+        let oldest = model.prompt.as_ref().map(|p| p.input.value().to_string());
+        update(&mut model, Message::RecallCommand(Direction::Backward));
+        update(&mut model, Message::RecallCommand(Direction::Forward));
+        let newest = model.prompt.as_ref().map(|p| p.input.value().to_string());
+        update(&mut model, Message::RecallCommand(Direction::Forward));
+
+        // THEN
+        assert_eq!(model.command_history, vec!["top", "sort -n"]);
+        assert_eq!(oldest.as_deref(), Some("top"));
+        assert_eq!(newest.as_deref(), Some("sort -n"));
+        assert_eq!(model.prompt.as_ref().map(|p| p.input.value()), Some(""));
     }
 
     #[test]
-    fn selection_can_be_reset() {
+    fn tab_completes_commands() {
         // GIVEN
-        let lines: Vec<String> = (0..5).map(|n| n.to_string()).collect();
-        let mut model = Model::default("file.txt".to_string(), &lines, false);
-        model.lines.state.select(Some(1));
+        let lines: Vec<String> = (1..=3).map(|n| n.to_string()).collect();
+        let mut model = Model::default(
+            Target::File("file.txt".to_string()),
+            Lines::from(&lines),
+            false,
+        );
+        update(&mut model, Message::OpenPrompt(PromptKind::Command));
+        type_into_prompt(&mut model, "shu");
 
         // WHEN
-        let _ = toggle_current(&mut model);
-        let _ = toggle_current(&mut model);
-        let _ = toggle_current(&mut model);
-        let message = go_back_or_quit(&mut model);
+        update(&mut model, Message::CompleteCommand);
 
         // THEN
-        assert!(message.is_none());
-        let statuses: Vec<bool> = model.lines.items.iter().map(|i| i.status).collect();
+        assert_eq!(
+            model.prompt.as_ref().map(|p| p.input.value()),
+            Some("shuffle ")
+        );
+    }
 
-        assert_eq!(statuses, vec![false, false, false, false, false]);
-        assert_eq!(model.selected_count, 0);
-        assert_eq!(model.lines.state.selected(), Some(4));
+    #[test]
+    fn write_command_writes_to_another_file() {
+        // GIVEN
+        let (mut model, path, _dir) = model_for_file("write-to", "0\n1\n2\n");
+        let other = path.with_file_name("other.txt");
+        update(&mut model, Message::SwitchWithNextItem);
+
+        // WHEN
+        run_command(&mut model, &format!("w {}", other.display()));
+
+        // THEN
+        assert!(matches!(model.message, Some(UserMessage::Success(_))));
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "1\n0\n2\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "0\n1\n2\n");
     }
 }
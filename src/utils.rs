@@ -1,19 +1,544 @@
-use std::fs::File;
-use std::io::BufReader;
+use clap::ValueEnum;
+use std::fs::{self, File};
+use std::hash::{DefaultHasher, Hasher};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-pub(crate) fn read_from_file(file: &File) -> Result<Vec<String>, std::io::Error> {
-    let reader = BufReader::new(file);
-    let lines = reader
-        .lines()
-        .collect::<Result<Vec<String>, std::io::Error>>()?;
+/// How the previous contents of a file are kept around before it's overwritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum BackupMode {
+    /// Keep a single backup at `<file>~`
+    Simple,
+    /// Keep every backup at `<file>.~N~`
+    Numbered,
+}
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// How the bytes of a line are decoded for display. Lines are always written
+/// back with their original bytes, regardless of the encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub(crate) enum Encoding {
+    /// UTF-8; invalid byte sequences are shown as U+FFFD
+    #[default]
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    /// ISO-8859-1 (Latin-1)
+    #[value(name = "latin1", alias = "iso-8859-1")]
+    Latin1,
+}
+
+impl Encoding {
+    pub(crate) fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Latin1 => bytes.iter().map(|b| char::from(*b)).collect(),
+        }
+    }
+
+    /// Bytes for text typed in shfl; `None` if some of it can't be represented
+    /// in the encoding.
+    pub(crate) fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Some(text.as_bytes().to_vec()),
+            Encoding::Latin1 => text.chars().map(|c| u8::try_from(c).ok()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+}
+
+/// A line's bytes along with the line break that ended it, which stays with
+/// the line wherever it's moved.
+pub(crate) type LineRef<'a> = (&'a [u8], Option<LineEnding>);
+
+/// A line as read from a file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct RawLine {
+    /// The line's bytes, without its line break
+    pub(crate) bytes: Vec<u8>,
+    /// `None` for lines that didn't have one: the last line of a file without
+    /// a trailing newline, or lines typed in shfl
+    pub(crate) ending: Option<LineEnding>,
+}
+
+impl From<Vec<u8>> for RawLine {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            ending: None,
+        }
+    }
+}
+
+/// How lines are laid out in a file, so that it can be written back the same
+/// way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileFormat {
+    /// Line break for lines that don't have their own
+    pub(crate) line_ending: LineEnding,
+    pub(crate) trailing_newline: bool,
+    pub(crate) bom: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+            bom: false,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Joins lines the way they were laid out in the original file, each
+    /// with its own line break. Whether the last one ends with a line break
+    /// depends on the file, not on the line.
+    pub(crate) fn join(&self, lines: &[LineRef]) -> Vec<u8> {
+        let mut content = Vec::new();
+        if self.bom {
+            content.extend_from_slice(BOM);
+        }
+        for (i, (bytes, ending)) in lines.iter().enumerate() {
+            content.extend_from_slice(bytes);
+            if i + 1 < lines.len() || self.trailing_newline {
+                content.extend_from_slice(ending.unwrap_or(self.line_ending).as_bytes());
+            }
+        }
+        content
+    }
+}
+
+/// Identifies a version of a file's contents, so that changes made to it by
+/// other programs can be detected before overwriting them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl Fingerprint {
+    fn new(metadata: &fs::Metadata, content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(content);
+
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// Reads the lines of a file along with their format and a fingerprint of the
+/// file's contents.
+pub(crate) fn read_lines_with_fingerprint(
+    file_path: &str,
+) -> Result<(Vec<RawLine>, FileFormat, Fingerprint), std::io::Error> {
+    let mut file = File::open(file_path)?;
+    let metadata = file.metadata()?;
+    let mut content = Vec::with_capacity(metadata.len() as usize);
+    file.read_to_end(&mut content)?;
+
+    let (lines, format) = read_from_file(content.as_slice())?;
+
+    Ok((lines, format, Fingerprint::new(&metadata, &content)))
+}
+
+pub(crate) fn fingerprint_file(file_path: &str) -> Result<Fingerprint, std::io::Error> {
+    let content = fs::read(file_path)?;
+    let metadata = fs::metadata(file_path)?;
+
+    Ok(Fingerprint::new(&metadata, &content))
+}
+
+pub(crate) fn read_from_file<R: Read>(
+    mut file: R,
+) -> Result<(Vec<RawLine>, FileFormat), std::io::Error> {
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+
+    let bom = content.starts_with(BOM);
+    let content = content.strip_prefix(BOM).unwrap_or(&content);
+
+    let mut lines = vec![];
+    let mut rest = content;
+    while let Some(i) = rest.iter().position(|b| *b == b'\n') {
+        let (bytes, ending) = match rest[..i].strip_suffix(b"\r") {
+            Some(bytes) => (bytes, LineEnding::Crlf),
+            None => (&rest[..i], LineEnding::Lf),
+        };
+        lines.push(RawLine {
+            bytes: bytes.to_vec(),
+            ending: Some(ending),
+        });
+        rest = &rest[i + 1..];
+    }
+    if !rest.is_empty() {
+        lines.push(RawLine::from(rest.to_vec()));
+    }
+
+    // the style of the first line break is used for lines typed in shfl
+    let line_ending = lines
+        .first()
+        .and_then(|line| line.ending)
+        .unwrap_or_default();
+    let trailing_newline = content.ends_with(b"\n");
+
+    Ok((
+        lines,
+        FileFormat {
+            line_ending,
+            trailing_newline,
+            bom,
+        },
+    ))
+}
+
+/// Like [`write_to_file`], but creates the file if it doesn't exist yet.
+pub(crate) fn write_to_path(
+    data: &[LineRef],
+    file_path: &str,
+    format: &FileFormat,
+    backup: Option<BackupMode>,
+) -> Result<(), std::io::Error> {
+    match Path::new(file_path).exists() {
+        true => write_to_file(data, file_path, format, backup),
+        false => fs::write(file_path, format.join(data)),
+    }
+}
+
+/// Writes lines to a file atomically: the content goes to a temporary file in
+/// the same directory, which is synced to disk and then renamed over the
+/// original. Symlinks are followed, and the original file's permissions are
+/// kept.
+pub(crate) fn write_to_file(
+    data: &[LineRef],
+    file_path: &str,
+    format: &FileFormat,
+    backup: Option<BackupMode>,
+) -> Result<(), std::io::Error> {
+    let target = fs::canonicalize(file_path)?;
+    let permissions = fs::metadata(&target)?.permissions();
+
+    if let Some(mode) = backup {
+        create_backup(&target, mode)?;
+    }
+
+    let content = format.join(data);
+    let temp_path = temp_path_for(&target);
 
-    Ok(lines)
+    let write_result = (|| {
+        let mut temp_file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        temp_file.write_all(&content)?;
+        temp_file.set_permissions(permissions)?;
+        temp_file.sync_all()?;
+        fs::rename(&temp_path, &target)
+    })();
+
+    if write_result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return write_result;
+    }
+
+    sync_parent_dir(&target)
+}
+
+fn temp_path_for(target: &Path) -> PathBuf {
+    let file_name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    target.with_file_name(format!(".{file_name}.shfl-{}.tmp", std::process::id()))
+}
+
+fn create_backup(target: &Path, mode: BackupMode) -> Result<(), std::io::Error> {
+    let backup_path = match mode {
+        BackupMode::Simple => append_to_file_name(target, "~"),
+        BackupMode::Numbered => {
+            let mut number = 1;
+            loop {
+                let candidate = append_to_file_name(target, &format!(".~{number}~"));
+                if !candidate.exists() {
+                    break candidate;
+                }
+                number += 1;
+            }
+        }
+    };
+
+    fs::copy(target, &backup_path).map(|_| ())
+}
+
+fn append_to_file_name(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+// the rename itself only becomes durable once the directory entry is synced
+#[cfg(unix)]
+fn sync_parent_dir(target: &Path) -> Result<(), std::io::Error> {
+    match target.parent() {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
 }
 
-pub(crate) fn write_to_file(data: Vec<&str>, file_path: &str) -> Result<(), std::io::Error> {
-    let mut file = File::options().write(true).truncate(true).open(file_path)?;
+#[cfg(not(unix))]
+fn sync_parent_dir(_target: &Path) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed<'a>(lines: &[&'a [u8]]) -> Vec<LineRef<'a>> {
+        lines.iter().map(|line| (*line, None)).collect()
+    }
+
+    fn refs(lines: &[RawLine]) -> Vec<LineRef<'_>> {
+        lines
+            .iter()
+            .map(|line| (line.bytes.as_slice(), line.ending))
+            .collect()
+    }
+
+    fn bytes(lines: &[RawLine]) -> Vec<&[u8]> {
+        lines.iter().map(|line| line.bytes.as_slice()).collect()
+    }
+
+    // removed when dropped
+    fn temp_dir(name: &str) -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix(&format!("shfl-{name}-"))
+            .tempdir()
+            .expect("temp dir should've been created")
+    }
+
+    #[test]
+    fn fingerprint_changes_when_file_is_modified() {
+        // GIVEN
+        let dir = temp_dir("fingerprint");
+        let path = dir.path().join("lines.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+        let (lines, _, on_load) = read_lines_with_fingerprint(path.to_str().unwrap()).unwrap();
+        let unchanged = fingerprint_file(path.to_str().unwrap()).unwrap();
+
+        // WHEN
+        fs::write(&path, "a\nc\n").unwrap();
+        let changed = fingerprint_file(path.to_str().unwrap()).unwrap();
+
+        // THEN
+        assert_eq!(bytes(&lines), vec![b"a", b"b"]);
+        assert_eq!(on_load, unchanged);
+        assert_ne!(on_load, changed);
+    }
+
+    #[test]
+    fn read_from_file_detects_format() {
+        // GIVEN
+        let content = "\u{feff}a\r\nb\r\nc";
+
+        // WHEN
+        let (lines, format) = read_from_file(content.as_bytes()).unwrap();
+
+        // THEN
+        assert_eq!(bytes(&lines), vec![b"a", b"b", b"c"]);
+        assert_eq!(
+            format,
+            FileFormat {
+                line_ending: LineEnding::Crlf,
+                trailing_newline: false,
+                bom: true,
+            }
+        );
+    }
+
+    #[test]
+    fn file_format_round_trips_content() {
+        // GIVEN
+        let contents: [&[u8]; 8] = [
+            b"",
+            b"\n",
+            b"a\n\nb\n",
+            b"a\r\nb\r\n",
+            b"a\r\nb",
+            b"\xef\xbb\xbfa\nb",
+            b"a\nb\r\nc\n",
+            b"caf\xe9\n\xff\xfe\n",
+        ];
+
+        // WHEN
+        let written = contents.map(|content| {
+            let (lines, format) = read_from_file(content).unwrap();
+            format.join(&refs(&lines))
+        });
+
+        // THEN
+        assert_eq!(written, contents.map(<[u8]>::to_vec));
+    }
+
+    #[test]
+    fn lines_keep_their_own_line_break_when_reordered() {
+        // GIVEN
+        let (lines, format) = read_from_file(b"a\r\nb\nc\r\nd".as_slice()).unwrap();
+
+        // WHEN
+        let reversed: Vec<RawLine> = lines.into_iter().rev().collect();
+
+        // THEN
+        // d never had a line break, so it gets the file's first one
+        assert_eq!(format.join(&refs(&reversed)), b"d\r\nc\r\nb\na");
+    }
+
+    #[test]
+    fn encodings_decode_lines_for_display() {
+        // GIVEN
+        let lines: [(Encoding, &[u8]); 3] = [
+            (Encoding::Utf8, b"caf\xc3\xa9"),
+            (Encoding::Utf8, b"caf\xe9"),
+            (Encoding::Latin1, b"caf\xe9"),
+        ];
+
+        // WHEN
+        let decoded = lines.map(|(encoding, line)| encoding.decode(line));
+
+        // THEN
+        assert_eq!(decoded, ["café", "caf\u{fffd}", "café"]);
+    }
+
+    #[test]
+    fn write_to_file_replaces_contents_and_leaves_no_temp_file() {
+        // GIVEN
+        let dir = temp_dir("write");
+        let path = dir.path().join("lines.txt");
+        fs::write(&path, "a\nb\nc\n").unwrap();
+
+        // WHEN
+        let result = write_to_file(
+            &typed(&[b"c", b"a"]),
+            path.to_str().unwrap(),
+            &FileFormat::default(),
+            None,
+        );
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "c\na\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_to_file_follows_symlinks_and_keeps_permissions() {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+
+        // GIVEN
+        let dir = temp_dir("symlink");
+        let path = dir.path().join("lines.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink(&path, &link).unwrap();
+
+        // WHEN
+        let result = write_to_file(
+            &typed(&[b"b", b"a"]),
+            link.to_str().unwrap(),
+            &FileFormat::default(),
+            None,
+        );
+
+        // THEN
+        assert!(result.is_ok());
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\na\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn write_to_file_keeps_simple_backup() {
+        // GIVEN
+        let dir = temp_dir("simple-backup");
+        let path = dir.path().join("lines.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+
+        // WHEN
+        let _ = write_to_file(
+            &typed(&[b"b", b"a"]),
+            path.to_str().unwrap(),
+            &FileFormat::default(),
+            Some(BackupMode::Simple),
+        );
+        let result = write_to_file(
+            &typed(&[b"a", b"b"]),
+            path.to_str().unwrap(),
+            &FileFormat::default(),
+            Some(BackupMode::Simple),
+        );
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lines.txt~")).unwrap(),
+            "b\na\n"
+        );
+    }
+
+    #[test]
+    fn write_to_file_keeps_numbered_backups() {
+        // GIVEN
+        let dir = temp_dir("numbered-backup");
+        let path = dir.path().join("lines.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+
+        // WHEN
+        let _ = write_to_file(
+            &typed(&[b"b", b"a"]),
+            path.to_str().unwrap(),
+            &FileFormat::default(),
+            Some(BackupMode::Numbered),
+        );
+        let result = write_to_file(
+            &typed(&[b"a", b"b"]),
+            path.to_str().unwrap(),
+            &FileFormat::default(),
+            Some(BackupMode::Numbered),
+        );
 
-    let content = data.join("\n") + "\n";
-    file.write(content.as_bytes()).map(|_| ())
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lines.txt.~1~")).unwrap(),
+            "a\nb\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lines.txt.~2~")).unwrap(),
+            "b\na\n"
+        );
+    }
 }
@@ -1,65 +1,351 @@
-use crate::common::{PRIMARY_COLOR, TITLE, TITLE_FG_COLOR, View};
-use crate::model::Model;
+use crate::command::candidates;
+use crate::common::{
+    CONFLICT_PROMPT, DEDUPE_MENU, Menu, PromptKind, REGISTER_MENU, SELECT_MENU, SORT_MENU, TITLE,
+    View,
+};
+use crate::external::PLACEHOLDER;
+use crate::input::TextInput;
+use crate::model::{EditTarget, LineItem, Model, Prompt, Target};
+use crate::search::Search;
+use crate::sort::KeySpec;
+use crate::theme::Theme;
 use ratatui::{
     Frame,
-    layout::Alignment,
+    layout::{Alignment, Position, Rect},
     style::Style,
-    text::Line,
-    widgets::{Block, List, ListDirection, ListItem, Padding, Paragraph},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListDirection, ListItem, Padding, Paragraph},
 };
-
-const HELP_CONTENTS: &str = include_str!("static/help.txt");
+use std::path::Path;
 
 pub(crate) fn view(model: &mut Model, frame: &mut Frame) {
     match model.view {
         View::List => render_list_view(model, frame),
-        View::Help => render_help_view(frame),
+        View::Help => render_help_view(model, frame),
     }
 }
 
 fn render_list_view(model: &mut Model, frame: &mut Frame) {
-    let items: Vec<ListItem> = model.lines.items.iter().map(ListItem::from).collect();
-
-    let title = model
-        .message
-        .as_ref()
-        .map(|m| format!(" {}", m.value()))
-        .unwrap_or(TITLE.to_string());
-
-    let base_title_style = Style::new().bold();
-    let title_style = match model.message {
-        Some(_) => base_title_style,
-        None => base_title_style.bg(PRIMARY_COLOR).fg(TITLE_FG_COLOR),
+    // the bottom row holds the title
+    model.list_height = frame.area().height.saturating_sub(1) as usize;
+    let visible = model.lines.visible();
+    let occurrences = model.lines.occurrences();
+    let mut items: Vec<ListItem> = visible
+        .iter()
+        .map(|i| {
+            let item = &model.lines.items[*i];
+            let count = occurrences.get(item.raw.as_ref()).copied().unwrap_or(1);
+            list_item(item, count, model.search.as_ref(), &model.theme)
+        })
+        .collect();
+
+    // new lines get an empty row to be typed into, which the cursor is moved
+    // to only while rendering
+    let mut state = model.lines.state;
+    let new_line_row = match &model.edit {
+        Some(edit) => match edit.target {
+            EditTarget::Insert(index) => Some(visible.partition_point(|i| *i < index)),
+            EditTarget::Replace(_) => None,
+        },
+        None => None,
     };
+    if let Some(position) = new_line_row {
+        items.insert(position, ListItem::new(""));
+        state.select(Some(position));
+    }
 
-    let block = Block::default()
-        .title_bottom(title)
-        .title_style(title_style);
+    let bold = Style::new().bold();
+    let title = match (&model.key_job, &model.conflict, &model.prompt, model.menu) {
+        (Some(job), _, _, _) => Line::styled(
+            format!(
+                " computing sort keys: {}/{} [esc] cancel ",
+                job.done, job.total
+            ),
+            bold,
+        ),
+        (None, Some(_), _, _) => Line::styled(CONFLICT_PROMPT, bold),
+        (None, None, Some(p), _) => Line::styled(prompt_title(p, model), bold),
+        (None, None, None, Some(menu)) => Line::styled(menu_title(menu), bold),
+        (None, None, None, None) => status_bar(model, &visible),
+    };
+
+    if model.key_job.is_none()
+        && model.conflict.is_none()
+        && let Some(prompt) = &model.prompt
+    {
+        let area = frame.area();
+        let x =
+            Line::from(prompt_prefix(prompt.kind)).width() as u16 + cursor_column(&prompt.input);
+        frame.set_cursor_position(Position::new(
+            area.x + x.min(area.width.saturating_sub(1)),
+            area.bottom().saturating_sub(1),
+        ));
+    }
+
+    let block = Block::default().title_bottom(title);
 
     let list = List::new(items)
         .block(block)
-        .style(Style::new().white())
+        .style(model.theme.text_style())
         .repeat_highlight_symbol(true)
         .highlight_symbol(">> ")
-        .highlight_style(Style::new().fg(PRIMARY_COLOR))
+        .highlight_style(model.theme.cursor_style())
         .direction(ListDirection::TopToBottom);
 
-    frame.render_stateful_widget(list, frame.area(), &mut model.lines.state)
+    frame.render_stateful_widget(list, frame.area(), &mut state);
+    match new_line_row {
+        Some(_) => *model.lines.state.offset_mut() = state.offset(),
+        None => model.lines.state = state,
+    }
+
+    if let Some(edit) = &model.edit {
+        let area = frame.area();
+        let row = state.selected().unwrap_or(0).saturating_sub(state.offset()) as u16;
+        // lines up with the text after the highlight symbol
+        let input_area =
+            Rect::new(area.x + 3, area.y + row, area.width.saturating_sub(3), 1).intersection(area);
+        render_text_input(frame, input_area, &edit.input, model.theme.cursor_style());
+    }
+}
+
+/// Draws a single line of editable text, scrolled sideways so that the cursor
+/// stays in view, and places the terminal's cursor in it.
+fn render_text_input(frame: &mut Frame, area: Rect, input: &TextInput, style: Style) {
+    if area.is_empty() {
+        return;
+    }
+
+    let cursor = cursor_column(input);
+    let scroll = cursor.saturating_sub(area.width - 1);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(input.value())
+            .style(style)
+            .scroll((0, scroll)),
+        area,
+    );
+    frame.set_cursor_position(Position::new(area.x + cursor - scroll, area.y));
+}
+
+// width of the text before the cursor, in terminal cells
+fn cursor_column(input: &TextInput) -> u16 {
+    let before_cursor: String = input.value().chars().take(input.cursor()).collect();
+    Line::from(before_cursor).width() as u16
+}
+
+// lines that show up more than once get their number of occurrences next to
+// them
+fn list_item<'a>(
+    item: &'a LineItem,
+    occurrences: usize,
+    search: Option<&Search>,
+    theme: &Theme,
+) -> ListItem<'a> {
+    let style = match item.status {
+        true => theme.selected_style(),
+        false => Style::new(),
+    };
+    let mut spans = vec![];
+    if item.status {
+        spans.push(Span::styled("> ", style));
+    }
+
+    let mut last = 0;
+    for range in search
+        .map(|s| s.match_ranges(&item.content))
+        .unwrap_or_default()
+    {
+        spans.push(Span::styled(&item.content[last..range.start], style));
+        spans.push(Span::styled(
+            &item.content[range.clone()],
+            style.patch(theme.match_style()),
+        ));
+        last = range.end;
+    }
+    spans.push(Span::styled(&item.content[last..], style));
+    if occurrences > 1 {
+        spans.push(Span::styled(
+            format!("  ×{occurrences}"),
+            theme.duplicate_style(),
+        ));
+    }
+
+    ListItem::new(Line::from(spans))
+}
+
+/// Mode, file, position and selection, shown at the bottom when nothing else
+/// needs it; messages go at the end until they expire.
+fn status_bar(model: &Model, visible: &[usize]) -> Line<'static> {
+    let mode = match (&model.edit, &model.visual, &model.lines.filter) {
+        (Some(_), _, _) => "EDIT".to_string(),
+        (None, Some(_), _) => "VISUAL".to_string(),
+        (None, None, Some(f)) => format!("FILTER: {}", f.query),
+        (None, None, None) => "NORMAL".to_string(),
+    };
+    let mut file = match &model.target {
+        Target::File(path) => Path::new(path)
+            .file_name()
+            .map_or(path.clone(), |name| name.to_string_lossy().to_string()),
+        Target::Stdout => "stdin".to_string(),
+    };
+    if model.is_modified() {
+        file.push_str(" [+]");
+    }
+    // the list state's index can run past the end until it's rendered
+    let position = match model.lines.state.selected() {
+        Some(p) if !visible.is_empty() => p.min(visible.len() - 1) + 1,
+        _ => 0,
+    };
+    let position = match &model.lines.filter {
+        Some(_) => format!(
+            "{position}/{} of {}",
+            visible.len(),
+            model.lines.items.len()
+        ),
+        None => format!("{position}/{}", visible.len()),
+    };
+
+    let mut parts = vec![TITLE.trim().to_string(), mode, file, position];
+    let selected = model.lines.selected_count();
+    if selected > 0 {
+        parts.push(format!("{selected} selected"));
+    }
+    if let Some(seed) = model.last_seed {
+        parts.push(format!("seed {seed}"));
+    }
+    if model.edit.is_some() {
+        parts.push("[enter] save / [esc] cancel".to_string());
+    }
+    let keys = pending_keys(model);
+    if !keys.is_empty() {
+        parts.push(keys);
+    }
+
+    let mut spans = vec![Span::styled(
+        format!(" {} ", parts.join(" | ")),
+        model.theme.title_style(),
+    )];
+    if let Some(message) = &model.message {
+        spans.push(Span::styled(
+            format!(" {}", message.value()),
+            Style::new().bold(),
+        ));
+    }
+    Line::from(spans)
+}
+
+// the register, count and keys that apply to the next command, as typed
+fn pending_keys(model: &Model) -> String {
+    let mut keys = String::new();
+    if let Some(name) = model.pending_register {
+        keys.push_str(&format!("\"{name}"));
+    }
+    if let Some(count) = model.count {
+        keys.push_str(&format!("{count}"));
+    }
+    for chord in &model.partial_keys {
+        keys.push_str(&chord.to_string());
+    }
+    keys
+}
+
+fn prompt_prefix(kind: PromptKind) -> &'static str {
+    match kind {
+        PromptKind::Search => " /",
+        PromptKind::Filter => " filter: ",
+        PromptKind::SortKey => " sort by: ",
+        PromptKind::SortCommand => " sort by command: ",
+        PromptKind::Select => " select matching: ",
+        PromptKind::Command => " :",
+    }
+}
+
+fn menu_title(menu: Menu) -> &'static str {
+    match menu {
+        Menu::Sort => SORT_MENU,
+        Menu::Dedupe => DEDUPE_MENU,
+        Menu::Register => REGISTER_MENU,
+        Menu::Select => SELECT_MENU,
+    }
+}
+
+fn prompt_title(prompt: &Prompt, model: &Model) -> String {
+    let mut title = format!("{}{} ", prompt_prefix(prompt.kind), prompt.input.value());
+
+    if prompt.kind == PromptKind::SortKey {
+        if let Err(e) = KeySpec::parse(prompt.input.value()) {
+            title.push_str(&format!(" [{e}]"));
+        }
+        return title;
+    }
+    if prompt.kind == PromptKind::Command {
+        let candidates = candidates(prompt.input.value());
+        if !candidates.is_empty() {
+            title.push_str(&format!(" [{}]", candidates.join(" ")));
+        }
+        return title;
+    }
+    if prompt.kind == PromptKind::SortCommand {
+        if !prompt.input.value().contains(PLACEHOLDER) {
+            title.push_str(" [no {}, lines are passed on stdin]");
+        }
+        return title;
+    }
+
+    let (regex, valid) = match prompt.kind {
+        PromptKind::Search => {
+            if model.search.as_ref().is_some_and(|s| s.ignore_case) {
+                title.push_str(" [ignore case]");
+            }
+            model
+                .search
+                .as_ref()
+                .map(|s| (s.regex, s.is_valid()))
+                .unwrap_or((false, true))
+        }
+        PromptKind::Filter => model
+            .lines
+            .filter
+            .as_ref()
+            .map(|f| (f.regex, f.is_valid()))
+            .unwrap_or((false, true)),
+        PromptKind::Select => (
+            true,
+            Search::new(prompt.input.value(), true, false).is_valid(),
+        ),
+        PromptKind::SortKey | PromptKind::SortCommand | PromptKind::Command => (false, true),
+    };
+
+    if regex {
+        title.push_str(" [regex]");
+    } else if prompt.kind == PromptKind::Filter {
+        title.push_str(" [fuzzy]");
+    }
+    if !valid {
+        title.push_str(" [invalid regex]");
+    }
+
+    title
 }
 
-fn render_help_view(frame: &mut Frame) {
-    let title_style = Style::new().bold().bg(PRIMARY_COLOR).fg(TITLE_FG_COLOR);
+fn render_help_view(model: &Model, frame: &mut Frame) {
+    let title_style = model.theme.title_style();
 
     let block = Block::default()
         .title_bottom(TITLE)
         .padding(Padding::left(1))
         .title_style(title_style);
 
-    let lines: Vec<Line<'_>> = HELP_CONTENTS.lines().map(Line::from).collect();
+    let mut lines: Vec<Line<'_>> = ["Help", "===", "", "Keymaps", "---", ""]
+        .into_iter()
+        .map(Line::from)
+        .collect();
+    lines.extend(model.keymap.help().into_iter().map(Line::from));
 
     let p = Paragraph::new(lines)
         .block(block)
-        .style(Style::new().white())
+        .style(model.theme.text_style())
         .alignment(Alignment::Left);
 
     frame.render_widget(p, frame.area())
@@ -0,0 +1,45 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when a file changes on disk by periodically checking its metadata.
+#[derive(Debug)]
+pub(crate) struct FileWatcher {
+    path: String,
+    last_polled: Instant,
+    last_seen: Option<(SystemTime, u64)>,
+}
+
+impl FileWatcher {
+    pub(crate) fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            last_polled: Instant::now(),
+            last_seen: metadata_of(path),
+        }
+    }
+
+    /// Returns whether the file's modification time or size changed since the
+    /// last poll. Checks are throttled, so this is cheap to call on every tick
+    /// of the event loop.
+    pub(crate) fn poll(&mut self) -> bool {
+        if self.last_polled.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_polled = Instant::now();
+
+        let current = metadata_of(&self.path);
+        if current.is_none() || current == self.last_seen {
+            return false;
+        }
+
+        self.last_seen = current;
+        true
+    }
+}
+
+fn metadata_of(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}